@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDate;
+
+use crate::data::{Invoice, Provider, Trip, document_reference};
+use crate::expense::{Category, Expense};
+use crate::trip_filter::{Decision, Direction};
+
+/// Result of a run: the invoice it is based on, what is claimed and what is left out.
+/// Every report format renders one of these.
+pub struct Claim {
+    pub invoice: Invoice,
+    pub employee: Option<String>,
+    pub from: Vec<String>, // Commute route as given on the command line
+    pub to: Vec<String>,
+    pub trips: Vec<Trip>,
+    pub excluded: Vec<(Trip, Decision)>,
+    pub other: Vec<Box<dyn Expense>>, // Receipts claimed next to the commute
+}
+
+impl Claim {
+    pub fn subtotal(&self, provider: Provider) -> f32 {
+        self.trips
+            .iter()
+            .filter(|t| t.provider == provider)
+            .map(|t| t.total())
+            .sum()
+    }
+
+    /// Everything claimed, trips first
+    pub fn expenses(&self) -> impl Iterator<Item = &dyn Expense> {
+        self.trips
+            .iter()
+            .map(|trip| trip as &dyn Expense)
+            .chain(self.other.iter().map(|expense| expense.as_ref()))
+    }
+
+    /// Categories of the expenses next to the commute, each gets its own subtotal
+    pub fn other_categories(&self) -> BTreeSet<Category> {
+        self.other
+            .iter()
+            .map(|expense| expense.category())
+            .collect()
+    }
+
+    pub fn category_total(&self, category: Category) -> f32 {
+        self.expenses()
+            .filter(|expense| expense.category() == category)
+            .map(|expense| expense.amount())
+            .sum()
+    }
+
+    pub fn total(&self) -> f32 {
+        self.expenses().map(|expense| expense.amount()).sum()
+    }
+
+    /// Billing period of the invoice, or the dates of the claimed trips when it has none
+    pub fn period(&self) -> Option<(NaiveDate, NaiveDate)> {
+        self.invoice.period.or_else(|| {
+            let first = self.trips.iter().map(|t| t.date).min()?;
+            let last = self.trips.iter().map(|t| t.date).max()?;
+            Some((first, last))
+        })
+    }
+
+    /// Claimed expenses per year and month, as "YYYY-MM"
+    pub fn monthly_totals(&self) -> BTreeMap<String, f32> {
+        let mut totals: BTreeMap<String, f32> = BTreeMap::new();
+        for expense in self.expenses() {
+            *totals
+                .entry(expense.date().format("%Y-%m").to_string())
+                .or_default() += expense.amount();
+        }
+        totals
+    }
+
+    /// Claimed journeys by number, as --explain lists them. Legs added for a missing
+    /// direction aren't part of one.
+    pub fn journeys(&self) -> Vec<(usize, Direction, Vec<&Trip>)> {
+        let mut journeys: BTreeMap<usize, (Direction, Vec<&Trip>)> = BTreeMap::new();
+        for trip in &self.trips {
+            if let Some((number, direction)) = trip.journey {
+                journeys
+                    .entry(number)
+                    .or_insert((direction, Vec::new()))
+                    .1
+                    .push(trip);
+            }
+        }
+        journeys
+            .into_iter()
+            .map(|(number, (direction, legs))| (number, direction, legs))
+            .collect()
+    }
+
+    pub fn invoice_reference(&self) -> String {
+        document_reference(
+            self.invoice.number.as_deref(),
+            self.invoice.date,
+            &self.invoice.file,
+        )
+    }
+
+    pub fn route(&self) -> String {
+        format!("{} - {}", self.from.join(", "), self.to.join(", "))
+    }
+}
+
+// Date with check-in and check-out times, when the source has them
+pub fn format_date(trip: &Trip) -> String {
+    match (trip.check_in, trip.check_out) {
+        (Some(check_in), Some(check_out)) => format!(
+            "{} {}-{}",
+            trip.date,
+            check_in.format("%H:%M"),
+            check_out.format("%H:%M")
+        ),
+        (Some(check_in), None) => format!("{} {}", trip.date, check_in.format("%H:%M")),
+        _ if trip.manual => format!("{} (manual)", trip.date),
+        _ => trip.date.to_string(),
+    }
+}
+
+// Price actually paid, with the supplements and refunds that make it up
+pub fn format_price(trip: &Trip) -> String {
+    let mut price = format!("{:.2}", trip.total());
+    for adjustment in &trip.adjustments {
+        price.push_str(&format!(
+            "\n{} {:+.2}",
+            adjustment.description, adjustment.amount
+        ));
+    }
+    price
+}
+
+pub fn format_vat(expense: &dyn Expense) -> String {
+    match expense.vat() {
+        Some((vat, rate)) => format!("{:.2} ({}%)", vat, rate),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+impl Claim {
+    /// Claim for the commute Hilversum - Amsterdam Centraal with nothing left out, tests
+    /// change what they need
+    pub fn for_test(invoice: Invoice, trips: Vec<Trip>) -> Claim {
+        Claim {
+            invoice,
+            employee: None,
+            from: vec!["Hilversum".to_string()],
+            to: vec!["Amsterdam Centraal".to_string()],
+            trips,
+            excluded: Vec::new(),
+            other: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::TelecomBill;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_totals_over_all_expenses() {
+        let date = |month, day| NaiveDate::from_ymd_opt(2025, month, day).unwrap();
+        let trip = Trip::new(
+            date(3, 31),
+            Provider::NS,
+            "Hilversum".to_string(),
+            "Amsterdam Centraal".to_string(),
+            5.6,
+        );
+        let bill = TelecomBill {
+            file: PathBuf::from("kpn.pdf"),
+            provider: "KPN".to_string(),
+            number: None,
+            date: None,
+            period: (date(4, 1), date(4, 30)),
+            amount: 50.0,
+            vat: Some((8.68, 21.0)),
+        };
+        let invoice = Invoice::new(PathBuf::from("invoice.pdf"), vec![trip.clone()], Vec::new());
+        let claim = Claim {
+            other: vec![Box::new(bill)],
+            ..Claim::for_test(invoice, vec![trip])
+        };
+
+        assert_eq!(claim.category_total(Category::Commute), 5.6);
+        assert_eq!(claim.category_total(Category::Internet), 50.0);
+        assert_eq!(claim.total(), 55.6);
+        assert_eq!(
+            claim.monthly_totals().into_iter().collect::<Vec<_>>(),
+            vec![("2025-03".to_string(), 5.6), ("2025-04".to_string(), 50.0)]
+        );
+        assert_eq!(
+            claim
+                .expenses()
+                .map(|expense| expense.evidence())
+                .collect::<Vec<_>>(),
+            vec![None, Some(std::path::Path::new("kpn.pdf"))]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::claim::Claim;
+use crate::data::Trip;
+
+/// One row per trip, claimed or not, with where it was found in the source file and the
+/// invoice it is on
+pub fn csv_report(claim: &Claim, output: &Path) -> Result<()> {
+    let mut writer = csv::Writer::from_path(output)?;
+    write_rows(claim, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_rows<W: std::io::Write>(claim: &Claim, writer: &mut csv::Writer<W>) -> Result<()> {
+    writer.write_record([
+        "Status",
+        "Reason",
+        "Date",
+        "Check-in",
+        "Provider",
+        "From",
+        "To",
+        "Price",
+        "File",
+        "Page",
+        "Lines",
+        "Left",
+        "Bottom",
+        "Right",
+        "Top",
+        "Invoice number",
+        "Invoice date",
+        "Period start",
+        "Period end",
+        "Customer number",
+        "Card number",
+    ])?;
+    // The same on every row, so each row can be read on its own
+    let invoice = &claim.invoice;
+    let (start, end) = invoice.period.unzip();
+    let invoice_fields = [
+        invoice.number.clone(),
+        invoice.date.map(|date| date.to_string()),
+        start.map(|date| date.to_string()),
+        end.map(|date| date.to_string()),
+        invoice.customer_number.clone(),
+        invoice.card_number.clone(),
+    ]
+    .map(Option::unwrap_or_default);
+    let claimed = claim
+        .trips
+        .iter()
+        .map(|trip| (trip, "claimed", String::new()));
+    let excluded = claim
+        .excluded
+        .iter()
+        .map(|(trip, decision)| (trip, "excluded", decision.to_string()));
+    for (trip, status, reason) in claimed.chain(excluded) {
+        let mut record = vec![
+            status.to_string(),
+            reason,
+            trip.date.to_string(),
+            trip.check_in
+                .map(|check_in| check_in.format("%H:%M").to_string())
+                .unwrap_or_default(),
+            trip.provider.to_string(),
+            trip.from.clone(),
+            trip.to.clone(),
+            format!("{:.2}", trip.total()),
+        ];
+        record.extend(source_fields(trip));
+        record.extend(invoice_fields.iter().cloned());
+        writer.write_record(&record)?;
+    }
+    Ok(())
+}
+
+// Pages and lines are 1-based here, as a reader counts them
+fn source_fields(trip: &Trip) -> Vec<String> {
+    let Some(source) = &trip.source else {
+        return vec![String::new(); 7];
+    };
+    let mut fields = vec![
+        source.file.display().to_string(),
+        source
+            .page
+            .map(|page| (page + 1).to_string())
+            .unwrap_or_default(),
+        match source.lines.len() {
+            1 => source.lines.end.to_string(),
+            _ => format!("{}-{}", source.lines.start + 1, source.lines.end),
+        },
+    ];
+    // The box on the page the row starts on, the JSON export has the rest
+    match source.bounds.first() {
+        Some((_, b)) => {
+            fields.extend([b.left, b.bottom, b.right, b.top].map(|v| format!("{:.1}", v)))
+        }
+        None => fields.extend(vec![String::new(); 4]),
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Invoice, Provider};
+    use crate::trip_filter::Decision;
+    use chrono::NaiveDate;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_csv_report_rows() {
+        let date = NaiveDate::from_ymd_opt(2025, 3, 3).unwrap();
+        let trip = |from: &str, to: &str| {
+            Trip::new(date, Provider::NS, from.to_string(), to.to_string(), 5.6)
+        };
+        let mut invoice = Invoice::new(
+            PathBuf::from("export.csv"),
+            vec![trip("Hilversum", "Amsterdam Centraal")],
+            Vec::new(),
+        );
+        invoice.card_number = Some("3528012345678901".to_string());
+        let trips = invoice.ns_trips.clone();
+        let claim = Claim {
+            excluded: vec![(trip("Hilversum", "Utrecht Centraal"), Decision::NotOnRoute)],
+            ..Claim::for_test(invoice, trips)
+        };
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        write_rows(&claim, &mut writer).unwrap();
+        let text = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows.len(), 3);
+        assert!(
+            rows[1].starts_with(
+                "claimed,,2025-03-03,,NS,Hilversum,Amsterdam Centraal,5.60,export.csv,"
+            )
+        );
+        assert!(rows[1].ends_with(",,,,,,3528012345678901"));
+        assert!(rows[2].starts_with("excluded,"));
+        assert!(rows[2].ends_with(",,,,,,,,,,,,,3528012345678901"));
+    }
+}
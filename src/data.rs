@@ -1,32 +1,385 @@
-use chrono::NaiveDate;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
-#[derive(Clone, Debug)]
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::trip_filter::Direction;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Provider {
     NS,
     GVB,
 }
 
+impl Provider {
+    /// Charged at check-in and settled at check-out, so a journey that costs exactly
+    /// this much was most likely never checked out
+    pub fn boarding_fare(&self) -> f32 {
+        match self {
+            Provider::NS => 20.0,
+            Provider::GVB => 4.0,
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provider::NS => write!(f, "NS"),
+            Provider::GVB => write!(f, "GVB"),
+        }
+    }
+}
+
+/// Layout of the invoice. Personal invoices come from a regular Mijn NS account,
+/// business ones from an NS-Businesscard and carry extra columns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvoiceFormat {
+    Personal,
+    Business,
+}
+
+/// Language the invoice was rendered in, follows the Mijn NS account setting
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    Dutch,
+    English,
+}
+
+/// Product ("kenmerk") a trip was travelled on, independent of the invoice language
+#[derive(Clone, Debug, PartialEq)]
+pub enum Product {
+    Peak,
+    OffPeak,
+    Weekend,
+    TrainJourneys,
+    Line(Option<u16>), // GVB line number, if printed
+}
+
+impl fmt::Display for Product {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Product::Peak => write!(f, "Peak"),
+            Product::OffPeak => write!(f, "Off-peak"),
+            Product::Weekend => write!(f, "Weekend"),
+            Product::TrainJourneys => write!(f, "Train journeys"),
+            Product::Line(Some(number)) => write!(f, "Line {}", number),
+            Product::Line(None) => write!(f, "Line"),
+        }
+    }
+}
+
+/// Fields that are only present on NS-Businesscard invoices
+#[derive(Clone, Debug, PartialEq)]
+pub struct BusinessDetails {
+    pub card_number: String,
+    pub card_holder: Option<String>,
+    pub cost_center: String,
+    pub vat_rate: f32,
+    pub vat: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdjustmentKind {
+    Supplement, // e.g. Intercity direct
+    Correction,
+    Refund, // e.g. forgotten check-out
+    Credit,
+}
+
+/// Non-trip invoice line that changes what was paid for a trip
+#[derive(Clone, Debug)]
+pub struct Adjustment {
+    pub kind: AdjustmentKind,
+    pub description: String,
+    pub amount: f32,            // Negative for refunds and credits
+    pub source: Option<Source>, // Adjustments are rows of their own on the invoice
+}
+
 #[derive(Clone, Debug)]
 pub struct Trip {
     pub date: NaiveDate,
+    pub check_in: Option<NaiveDateTime>, // Only when the source lists times
+    pub check_out: Option<NaiveDateTime>,
     pub provider: Provider, //NS or GVB
     pub from: String,
     pub to: String,
     pub price: f32,
+    pub product: Option<Product>,
+    pub business: Option<BusinessDetails>, // Only for business invoices
+    pub adjustments: Vec<Adjustment>,
+    pub manual: bool, // Added by the user, not on the invoice
+    pub source: Option<Source>,
+    pub journey: Option<(usize, Direction)>, // Claimed journey, numbered as --explain lists it
+}
+
+/// Where a trip was read from, so a reviewer can find the row in the original document
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Source {
+    pub file: PathBuf,
+    pub lines: Range<usize>, // Lines of the extracted text the row spans, or the CSV record
+    pub page: Option<usize>, // 0-based page the row starts on, PDFs only
+    pub bounds: Vec<(usize, Bounds)>, // Per page, a row can wrap over a page break
+    pub occurrence: usize,   // Earlier trips in the file with the same date, route and price
+}
+
+impl Source {
+    pub fn new(lines: Range<usize>) -> Source {
+        Source {
+            lines,
+            ..Default::default()
+        }
+    }
+}
+
+/// Rectangle on a PDF page in points, origin at the bottom left
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub left: f32,
+    pub bottom: f32,
+    pub right: f32,
+    pub top: f32,
+}
+
+impl Bounds {
+    pub fn union(self, other: Bounds) -> Bounds {
+        Bounds {
+            left: self.left.min(other.left),
+            bottom: self.bottom.min(other.bottom),
+            right: self.right.max(other.right),
+            top: self.top.max(other.top),
+        }
+    }
 }
 
 impl Trip {
     pub fn new(date: NaiveDate, provider: Provider, from: String, to: String, price: f32) -> Trip {
         Trip {
             date,
+            check_in: None,
+            check_out: None,
             provider,
             from,
             to,
             price,
+            product: None,
+            business: None,
+            adjustments: Vec::new(),
+            manual: false,
+            source: None,
+            journey: None,
+        }
+    }
+
+    /// What was actually paid for the trip: fare plus supplements, minus refunds and credits
+    pub fn total(&self) -> f32 {
+        self.price + self.adjustments.iter().map(|a| a.amount).sum::<f32>()
+    }
+
+    /// Missed check-out: no destination (or origin) on the invoice. Zero priced lines only
+    /// carry adjustments and are never journeys themselves.
+    pub fn is_incomplete(&self) -> bool {
+        self.price != 0.0 && (self.from.is_empty() || self.to.is_empty())
+    }
+
+    /// Both stations are known but the price is exactly the boarding fare. A ride can
+    /// really cost that much, so it is claimed, but the check-out is worth a look.
+    pub fn is_suspect(&self) -> bool {
+        !self.is_incomplete() && (self.price - self.provider.boarding_fare()).abs() < 0.005
+    }
+
+    pub fn price_cents(&self) -> i64 {
+        (self.total() * 100.0).round() as i64
+    }
+
+    pub fn with_source(mut self, source: Source) -> Trip {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_adjustment(mut self, adjustment: Adjustment) -> Trip {
+        self.adjustments.push(adjustment);
+        self
+    }
+
+    pub fn with_times(mut self, check_in: NaiveTime, check_out: Option<NaiveTime>) -> Trip {
+        let check_in = self.date.and_time(check_in);
+        self.check_out = check_out.map(|time| {
+            let check_out = self.date.and_time(time);
+            // Checked out after midnight
+            if check_out < check_in {
+                check_out + Duration::days(1)
+            } else {
+                check_out
+            }
+        });
+        self.check_in = Some(check_in);
+        self
+    }
+
+    pub fn with_product(mut self, product: Product) -> Trip {
+        self.product = Some(product);
+        self
+    }
+
+    pub fn with_business(mut self, details: BusinessDetails) -> Trip {
+        self.business = Some(details);
+        self
+    }
+}
+
+/// One parsed invoice: where it came from, what it says about itself and its trips
+#[derive(Clone, Debug)]
+pub struct Invoice {
+    pub file: PathBuf,
+    pub number: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub period: Option<(NaiveDate, NaiveDate)>, // Billing period, inclusive
+    pub customer_number: Option<String>,
+    pub card_number: Option<String>,
+    pub format: InvoiceFormat,
+    pub language: Language,
+    pub ns_trips: Vec<Trip>,
+    pub gvb_trips: Vec<Trip>,
+    pub totals: InvoiceTotals,
+    pub skipped: Vec<String>, // Rides with other operators, not read as trips
+}
+
+impl Invoice {
+    pub fn new(file: PathBuf, ns_trips: Vec<Trip>, gvb_trips: Vec<Trip>) -> Invoice {
+        let from_file = |trips: Vec<Trip>| -> Vec<Trip> {
+            trips
+                .into_iter()
+                .map(|mut trip| {
+                    trip.source.get_or_insert_with(Source::default).file = file.clone();
+                    for adjustment in &mut trip.adjustments {
+                        if let Some(source) = &mut adjustment.source {
+                            source.file = file.clone();
+                        }
+                    }
+                    trip
+                })
+                .collect()
+        };
+        let (mut ns_trips, mut gvb_trips) = (from_file(ns_trips), from_file(gvb_trips));
+        // Identical trips, e.g. two rides between the same stations on a day, are told apart
+        // by their order in the whole file, however many of them end up claimed
+        let mut seen: Vec<(NaiveDate, Provider, String, String, i64)> = Vec::new();
+        for trip in ns_trips.iter_mut().chain(gvb_trips.iter_mut()) {
+            let identity = (
+                trip.date,
+                trip.provider.clone(),
+                trip.from.clone(),
+                trip.to.clone(),
+                trip.price_cents(),
+            );
+            let occurrence = seen.iter().filter(|other| **other == identity).count();
+            seen.push(identity);
+            if let Some(source) = &mut trip.source {
+                source.occurrence = occurrence;
+            }
+        }
+        Invoice {
+            file,
+            number: None,
+            date: None,
+            period: None,
+            customer_number: None,
+            card_number: None,
+            format: InvoiceFormat::Personal,
+            language: Language::Dutch,
+            ns_trips,
+            gvb_trips,
+            totals: InvoiceTotals::default(),
+            skipped: Vec::new(),
         }
     }
 }
 
+/// Totals printed on the invoice itself, if it has them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InvoiceTotals {
+    pub ns: Option<f32>,
+    pub gvb: Option<f32>,
+    pub total: Option<f32>,
+}
+
+impl InvoiceTotals {
+    /// Printed totals that don't match the sum of the parsed trips, as (label, printed, parsed).
+    /// Trips have to be the complete, unfiltered lists.
+    pub fn differences(
+        &self,
+        ns_trips: &[Trip],
+        gvb_trips: &[Trip],
+    ) -> Vec<(&'static str, f32, f32)> {
+        let ns: f32 = ns_trips.iter().map(|t| t.total()).sum();
+        let gvb: f32 = gvb_trips.iter().map(|t| t.total()).sum();
+        [
+            ("NS", self.ns, ns),
+            ("GVB", self.gvb, gvb),
+            ("Total", self.total, ns + gvb),
+        ]
+        .into_iter()
+        .filter_map(|(label, printed, parsed)| match printed {
+            Some(printed) if (printed - parsed).abs() >= 0.005 => Some((label, printed, parsed)),
+            _ => None,
+        })
+        .collect()
+    }
+}
+
+/// Monthly phone or internet bill, claimed as internet allowance next to the commute
+#[derive(Clone, Debug)]
+pub struct TelecomBill {
+    pub file: PathBuf,
+    pub provider: String,
+    pub number: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub period: (NaiveDate, NaiveDate), // Inclusive
+    pub amount: f32,                    // Including VAT
+    pub vat: Option<(f32, f32)>,        // Amount and rate in percent, when the bill shows it
+}
+
+impl TelecomBill {
+    pub fn reference(&self) -> String {
+        document_reference(self.number.as_deref(), self.date, &self.file)
+    }
+}
+
+/// Invoice number and date, or the file name for documents without a number
+pub fn document_reference(number: Option<&str>, date: Option<NaiveDate>, file: &Path) -> String {
+    match (number, date) {
+        (Some(number), Some(date)) => format!("{} of {}", number, date),
+        (Some(number), None) => number.to_string(),
+        _ => file.display().to_string(),
+    }
+}
+
+/// NS stations and the GVB stops in the same GTFS parent station (stoparea), i.e. places
+/// where you change between train and tram/metro/bus on foot. (NS name, GVB name)
+pub const INTERCHANGES: [(&str, &str); 10] = [
+    ("Amsterdam Amstel", "Amstelstation"),
+    ("Amsterdam Bijlmer ArenA", "Station Bijlmer ArenA"),
+    ("Amsterdam Centraal", "Centraal Station"),
+    ("Amsterdam Holendrecht", "Station Holendrecht"),
+    ("Amsterdam Lelylaan", "Station Lelylaan"),
+    ("Amsterdam Muiderpoort", "Muiderpoortstation"),
+    ("Amsterdam RAI", "Station RAI"),
+    ("Amsterdam Science Park", "Station Science Park"),
+    ("Amsterdam Sloterdijk", "Station Sloterdijk"),
+    ("Amsterdam Zuid", "Station Zuid"),
+];
+
+/// Whether two stations, of the same or different operators, are the same place to transfer at
+pub fn same_interchange(a: &str, b: &str) -> bool {
+    a == b
+        || INTERCHANGES
+            .iter()
+            .any(|(ns, gvb)| (a == *ns && b == *gvb) || (a == *gvb && b == *ns))
+}
+
 pub const ALL_NS_STATIONS: [&str; 399] = [
     "Aalten",
     "Abcoude",
@@ -0,0 +1,393 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use pdfium_render::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::claim::Claim;
+use crate::data::{Bounds, Source, Trip};
+
+const LABEL_SIZE: f32 = 8.0;
+const LABEL_WIDTH: f32 = 18.0;
+
+// Claimed row on the invoice, numbered like the journeys in the reports
+#[derive(Debug, PartialEq)]
+struct Highlight {
+    page: usize,
+    bounds: Bounds,
+    journey: usize,
+    label: bool,
+}
+
+/// Copy of the PDF invoice with the claimed rows highlighted and numbered by journey, to
+/// attach as evidence next to the claim
+pub fn annotated_invoice(claim: &Claim, output: &Path) -> Result<()> {
+    let input = &claim.invoice.file;
+    if input.extension().is_none_or(|ext| ext != "pdf") {
+        bail!(
+            "Only PDF invoices can be annotated, {} isn't one",
+            input.display()
+        );
+    }
+    let pdfium = Pdfium::default();
+    let mut document = pdfium.load_pdf_from_file(input, None)?;
+    let font = document.fonts_mut().helvetica_bold();
+
+    for highlight in highlights(claim) {
+        let mut page = document.pages().get(highlight.page as u16)?;
+        let bounds = highlight.bounds;
+        page.objects_mut().create_path_object_rect(
+            PdfRect::new_from_values(
+                bounds.bottom - 1.0,
+                bounds.left - 2.0,
+                bounds.top + 1.0,
+                bounds.right + 2.0,
+            ),
+            None,
+            None,
+            Some(PdfColor::new(255, 220, 0, 90)),
+        )?;
+        // The number goes on the part of the row on the page it starts on
+        if !highlight.label {
+            continue;
+        }
+        // Next to the row, or in front of it when the row runs up to the page edge
+        let x = if bounds.right + 4.0 + LABEL_WIDTH < page.width().value {
+            bounds.right + 4.0
+        } else {
+            bounds.left - 4.0 - LABEL_WIDTH
+        };
+        page.objects_mut().create_text_object(
+            PdfPoints::new(x),
+            PdfPoints::new(bounds.bottom),
+            format!("#{}", highlight.journey),
+            font,
+            PdfPoints::new(LABEL_SIZE),
+        )?;
+    }
+
+    document.save_to_file(output)?;
+    Ok(())
+}
+
+/// Copy of the PDF invoice without the rows of the trips that weren't claimed. Their text is
+/// removed from the page, not only covered, and a black bar marks where it was.
+pub fn redacted_invoice(claim: &Claim, output: &Path) -> Result<()> {
+    let input = &claim.invoice.file;
+    if input.extension().is_none_or(|ext| ext != "pdf") {
+        bail!(
+            "Only PDF invoices can be redacted, {} isn't one",
+            input.display()
+        );
+    }
+    let pdfium = Pdfium::default();
+    let document = pdfium.load_pdf_from_file(input, None)?;
+
+    for (index, rows) in redactions(claim)? {
+        let rows: Vec<Bounds> = rows.into_iter().map(padded).collect();
+        let mut page = document.pages().get(index as u16)?;
+        let mut removed: Vec<usize> = Vec::new();
+        for (position, object) in page.objects().iter().enumerate() {
+            let kind = object.object_type();
+            if kind != PdfPageObjectType::Text && kind != PdfPageObjectType::XObjectForm {
+                continue;
+            }
+            let rect = object.bounds()?.to_rect();
+            let bounds = Bounds {
+                left: rect.left().value,
+                bottom: rect.bottom().value,
+                right: rect.right().value,
+                top: rect.top().value,
+            };
+            // Text nested in a form can't be taken out on its own
+            match (coverage(bounds, &rows), kind) {
+                (Coverage::Inside, PdfPageObjectType::Text) => removed.push(position),
+                (Coverage::Outside, _) => {}
+                _ => bail!(
+                    "Text on page {} runs into a row that should be redacted, the invoice can't be redacted safely",
+                    index + 1
+                ),
+            }
+        }
+        // From the back, so the positions stay valid
+        for position in removed.into_iter().rev() {
+            page.objects_mut().remove_object_at_index(position)?;
+        }
+        for row in rows {
+            page.objects_mut().create_path_object_rect(
+                PdfRect::new_from_values(row.bottom, row.left, row.top, row.right),
+                None,
+                None,
+                Some(PdfColor::new(0, 0, 0, 255)),
+            )?;
+        }
+    }
+
+    document.save_to_file(output)?;
+    Ok(())
+}
+
+// Rows of the trips that weren't claimed, with their supplements and refunds, per page. A
+// row that also belongs to a claimed trip stays. A row that can't be found on the page
+// would stay readable, so it fails the whole redaction.
+fn redactions(claim: &Claim) -> Result<BTreeMap<usize, Vec<Bounds>>> {
+    let claimed: Vec<(usize, Bounds)> = sources(claim.trips.iter())
+        .flat_map(|source| source.bounds.iter().copied())
+        .collect();
+    let mut pages: BTreeMap<usize, Vec<Bounds>> = BTreeMap::new();
+    for source in sources(claim.excluded.iter().map(|(trip, _)| trip)) {
+        if source.bounds.is_empty() {
+            bail!(
+                "Line {} of {} wasn't found on a page, the invoice can't be redacted safely",
+                source.lines.start + 1,
+                source.file.display()
+            );
+        }
+        for &(page, bounds) in &source.bounds {
+            if !claimed.contains(&(page, bounds)) {
+                pages.entry(page).or_default().push(bounds);
+            }
+        }
+    }
+    Ok(pages)
+}
+
+// Rows of the trips on the invoice and of their adjustments. Trips added by hand have none.
+fn sources<'a>(trips: impl Iterator<Item = &'a Trip>) -> impl Iterator<Item = &'a Source> {
+    trips
+        .flat_map(|trip| {
+            let adjustments = trip.adjustments.iter().map(|a| &a.source);
+            std::iter::once(&trip.source).chain(adjustments)
+        })
+        .flatten()
+}
+
+// Character boxes are tight, the bar covers a little more
+fn padded(bounds: Bounds) -> Bounds {
+    Bounds {
+        left: bounds.left - 2.0,
+        bottom: bounds.bottom - 1.0,
+        right: bounds.right + 2.0,
+        top: bounds.top + 1.0,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Coverage {
+    Inside,
+    Outside,
+    Partly, // Shares text with a row that stays
+}
+
+// Mostly inside a row counts as the row's text, a small overlap is the neighbouring line
+fn coverage(object: Bounds, rows: &[Bounds]) -> Coverage {
+    let area = |b: Bounds| (b.right - b.left).max(0.0) * (b.top - b.bottom).max(0.0);
+    let overlap: f32 = rows
+        .iter()
+        .map(|row| {
+            area(Bounds {
+                left: object.left.max(row.left),
+                bottom: object.bottom.max(row.bottom),
+                right: object.right.min(row.right),
+                top: object.top.min(row.top),
+            })
+        })
+        .sum();
+    let share = overlap / area(object).max(f32::EPSILON);
+    if share > 0.5 {
+        Coverage::Inside
+    } else if share > 0.1 {
+        Coverage::Partly
+    } else {
+        Coverage::Outside
+    }
+}
+
+// Trips added by hand or read from a CSV export have no place on a page and are skipped
+fn highlights(claim: &Claim) -> Vec<Highlight> {
+    let mut highlights = Vec::new();
+    for trip in &claim.trips {
+        let (Some((journey, _)), Some(source)) = (trip.journey, &trip.source) else {
+            continue;
+        };
+        for (part, &(page, bounds)) in source.bounds.iter().enumerate() {
+            highlights.push(Highlight {
+                page,
+                bounds,
+                journey,
+                label: part == 0,
+            });
+        }
+    }
+    highlights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Adjustment, AdjustmentKind, Invoice, Provider};
+    use crate::trip_filter::{Decision, Direction, trip_claim_split, trip_decisions};
+    use chrono::{Duration, NaiveDate};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_highlights_numbered_by_journey() {
+        let bounds = |bottom: f32| Bounds {
+            left: 40.0,
+            bottom,
+            right: 550.0,
+            top: bottom + 10.0,
+        };
+        let trip = |day, from: &str, to: &str, row: Option<f32>| {
+            let date = NaiveDate::from_ymd_opt(2025, 3, day).unwrap();
+            let mut source = Source::new(0..1);
+            source.page = row.map(|_| 0);
+            source.bounds = row.map(|bottom| (0, bounds(bottom))).into_iter().collect();
+            Trip::new(date, Provider::NS, from.to_string(), to.to_string(), 5.6).with_source(source)
+        };
+        let mut all_trips = vec![
+            trip(3, "Hilversum", "Amsterdam Centraal", Some(700.0)),
+            trip(3, "Amsterdam Centraal", "Hilversum", Some(680.0)),
+            trip(4, "Amsterdam Centraal", "Hilversum", Some(660.0)),
+            trip(5, "Hilversum", "Amsterdam Centraal", Some(640.0)),
+            trip(5, "Amsterdam Centraal", "Hilversum", Some(620.0)),
+        ];
+        let claim = Claim::for_test(
+            Invoice::new(PathBuf::from("invoice.pdf"), Vec::new(), Vec::new()),
+            Vec::new(),
+        );
+        let mut decisions = trip_decisions(
+            &all_trips,
+            &claim.from,
+            &claim.to,
+            Duration::minutes(60),
+            false,
+        );
+        // Claimed with --claim-missing, not on the invoice
+        all_trips.push(trip(4, "Hilversum", "Amsterdam Centraal", None));
+        decisions.push(Decision::Missing {
+            direction: Direction::ToWork,
+        });
+        decisions[3] = Decision::AlreadyClaimed {
+            claim: 1,
+            on: NaiveDate::from_ymd_opt(2025, 3, 31).unwrap(),
+        };
+        let (trips, excluded) = trip_claim_split(all_trips, decisions);
+        let claim = Claim {
+            trips,
+            excluded,
+            ..claim
+        };
+
+        // Numbered as --explain lists them
+        let journeys: Vec<(usize, f32)> = highlights(&claim)
+            .iter()
+            .map(|h| (h.journey, h.bounds.bottom))
+            .collect();
+        assert_eq!(
+            journeys,
+            vec![(1, 700.0), (2, 680.0), (3, 660.0), (5, 620.0)]
+        );
+    }
+
+    #[test]
+    fn test_redactions_keep_claimed_rows() {
+        let located = |page, bottom: f32| {
+            let mut source = Source::new(0..1);
+            source.page = Some(page);
+            source.bounds = vec![(
+                page,
+                Bounds {
+                    left: 40.0,
+                    bottom,
+                    right: 550.0,
+                    top: bottom + 10.0,
+                },
+            )];
+            source
+        };
+        let date = NaiveDate::from_ymd_opt(2025, 3, 8).unwrap();
+        let trip = |from: &str, to: &str| {
+            Trip::new(date, Provider::NS, from.to_string(), to.to_string(), 5.6)
+        };
+        let weekend = trip("Hilversum", "Utrecht Centraal")
+            .with_source(located(0, 700.0))
+            .with_adjustment(Adjustment {
+                kind: AdjustmentKind::Supplement,
+                description: "Toeslag Intercity direct".to_string(),
+                amount: 2.6,
+                source: Some(located(1, 500.0)),
+            });
+        let claimed = vec![trip("Hilversum", "Amsterdam Centraal").with_source(located(0, 680.0))];
+        let claim = Claim {
+            excluded: vec![
+                (weekend, Decision::Weekend),
+                // Shares its row with the claimed trip
+                (
+                    trip("Hilversum", "Amsterdam Centraal").with_source(located(0, 680.0)),
+                    Decision::ZeroPrice,
+                ),
+                (trip("Hilversum", "Baarn"), Decision::NotOnRoute),
+            ],
+            ..Claim::for_test(
+                Invoice::new(PathBuf::from("invoice.pdf"), Vec::new(), Vec::new()),
+                claimed,
+            )
+        };
+
+        let pages: Vec<(usize, Vec<f32>)> = redactions(&claim)
+            .unwrap()
+            .into_iter()
+            .map(|(page, rows)| (page, rows.iter().map(|b| b.bottom).collect()))
+            .collect();
+        assert_eq!(pages, vec![(0, vec![700.0]), (1, vec![500.0])]);
+    }
+
+    #[test]
+    fn test_redactions_refuse_unlocated_rows() {
+        let date = NaiveDate::from_ymd_opt(2025, 3, 8).unwrap();
+        let weekend = Trip::new(
+            date,
+            Provider::NS,
+            "Hilversum".to_string(),
+            "Utrecht Centraal".to_string(),
+            5.6,
+        )
+        .with_source(Source {
+            file: PathBuf::from("invoice.pdf"),
+            ..Source::new(11..12)
+        });
+        let claim = Claim {
+            excluded: vec![(weekend, Decision::Weekend)],
+            ..Claim::for_test(
+                Invoice::new(PathBuf::from("invoice.pdf"), Vec::new(), Vec::new()),
+                Vec::new(),
+            )
+        };
+
+        let error = redactions(&claim).unwrap_err().to_string();
+        assert!(error.starts_with("Line 12 of invoice.pdf wasn't found on a page"));
+    }
+
+    #[test]
+    fn test_coverage_of_text_objects() {
+        let row = Bounds {
+            left: 40.0,
+            bottom: 700.0,
+            right: 550.0,
+            top: 712.0,
+        };
+        let text = |bottom: f32, top: f32| Bounds {
+            left: 60.0,
+            bottom,
+            right: 200.0,
+            top,
+        };
+        assert_eq!(coverage(text(701.0, 711.0), &[row]), Coverage::Inside);
+        // Descenders of the line above
+        assert_eq!(coverage(text(711.0, 722.0), &[row]), Coverage::Outside);
+        // A text block spanning this row and the next two
+        assert_eq!(coverage(text(676.0, 712.0), &[row]), Coverage::Partly);
+        assert_eq!(coverage(text(600.0, 610.0), &[row]), Coverage::Outside);
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+use std::path::Path;
+
+use chrono::NaiveDate;
+
+use crate::data::{TelecomBill, Trip};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Commute,
+    Internet,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Category::Commute => write!(f, "Commute"),
+            Category::Internet => write!(f, "Internet"),
+        }
+    }
+}
+
+/// Anything that can be claimed. A new kind of receipt only needs a scanner and this trait
+/// to show up in every report and in the submitted expense report.
+pub trait Expense {
+    fn category(&self) -> Category;
+    fn date(&self) -> NaiveDate;
+    fn amount(&self) -> f32; // Including VAT
+    fn vat(&self) -> Option<(f32, f32)>; // Amount and rate in percent
+    fn description(&self) -> String;
+    // Receipt the expense can be checked against
+    fn evidence(&self) -> Option<&Path>;
+}
+
+impl Expense for Trip {
+    fn category(&self) -> Category {
+        Category::Commute
+    }
+
+    fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    fn amount(&self) -> f32 {
+        self.total()
+    }
+
+    // Only business invoices split it out
+    fn vat(&self) -> Option<(f32, f32)> {
+        self.business
+            .as_ref()
+            .map(|details| (details.vat, details.vat_rate))
+    }
+
+    fn description(&self) -> String {
+        format!("{} {} - {}", self.provider, self.from, self.to)
+    }
+
+    fn evidence(&self) -> Option<&Path> {
+        self.source.as_ref().map(|source| source.file.as_path())
+    }
+}
+
+impl Expense for TelecomBill {
+    fn category(&self) -> Category {
+        Category::Internet
+    }
+
+    // Bills count for the month their period starts in
+    fn date(&self) -> NaiveDate {
+        self.period.0
+    }
+
+    fn amount(&self) -> f32 {
+        self.amount
+    }
+
+    fn vat(&self) -> Option<(f32, f32)> {
+        self.vat
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "{} {} - {}, invoice {}",
+            self.provider,
+            self.period.0,
+            self.period.1,
+            self.reference()
+        )
+    }
+
+    fn evidence(&self) -> Option<&Path> {
+        Some(&self.file)
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::claim::Claim;
+
+/// Claim as it is handed to an expense-management service
+#[derive(Serialize, Debug)]
+pub struct ExpenseReport {
+    pub title: String,
+    pub employee: Option<String>,
+    pub currency: String,
+    pub total: f64,
+    pub expenses: Vec<ExpenseLine>,
+    #[serde(skip)]
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExpenseLine {
+    pub date: NaiveDate,
+    pub category: String,
+    pub description: String,
+    pub amount: f64,
+}
+
+#[derive(Debug)]
+pub struct Attachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+impl ExpenseReport {
+    // One line per claimed expense, with the receipts attached as evidence
+    pub fn from_claim(claim: &Claim) -> Result<ExpenseReport> {
+        let title = match claim.period() {
+            Some((start, end)) => format!("Commuting expenses {} - {}", start, end),
+            None => "Commuting expenses".to_string(),
+        };
+        let expenses = claim
+            .expenses()
+            .map(|expense| ExpenseLine {
+                date: expense.date(),
+                category: expense.category().to_string().to_lowercase(),
+                description: expense.description(),
+                amount: cents(expense.amount()),
+            })
+            .collect();
+        // Trips share their invoice, each receipt is attached once
+        let mut evidence: Vec<&Path> = Vec::new();
+        for file in claim.expenses().filter_map(|expense| expense.evidence()) {
+            if !evidence.contains(&file) {
+                evidence.push(file);
+            }
+        }
+        Ok(ExpenseReport {
+            title,
+            employee: claim.employee.clone(),
+            currency: "EUR".to_string(),
+            total: cents(claim.total()),
+            expenses,
+            attachments: evidence
+                .into_iter()
+                .map(Attachment::from_file)
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    // Sends another version of a receipt, e.g. the invoice with private trips redacted
+    pub fn replace_attachment(&mut self, original: &Path, replacement: &Path) -> Result<()> {
+        let file_name = original
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if let Some(attachment) = self
+            .attachments
+            .iter_mut()
+            .find(|attachment| attachment.file_name == file_name)
+        {
+            *attachment = Attachment::from_file(replacement)?;
+        }
+        Ok(())
+    }
+}
+
+impl Attachment {
+    pub fn from_file(file: &Path) -> Result<Attachment> {
+        let content_type = match file.extension().and_then(|ext| ext.to_str()) {
+            Some("pdf") => "application/pdf",
+            Some("csv") => "text/csv",
+            _ => "application/octet-stream",
+        };
+        Ok(Attachment {
+            file_name: file
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            content_type: content_type.to_string(),
+            data: std::fs::read(file).with_context(|| format!("Can't read {}", file.display()))?,
+        })
+    }
+}
+
+// f32 sums print as 30.199999, services expect amounts in cents precision
+pub fn cents(amount: f32) -> f64 {
+    (amount as f64 * 100.0).round() / 100.0
+}
+
+/// Request to an expense service, sent as multipart/form-data
+pub struct ExpenseRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub parts: Vec<Part>,
+}
+
+pub struct Part {
+    pub name: String,
+    pub file_name: Option<String>,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// An expense-management service. Implementations only know their own API, sending the
+/// request is shared.
+pub trait ExpenseClient {
+    fn request(&self, report: &ExpenseReport) -> Result<ExpenseRequest>;
+    // Reference of the created report, read from the response body
+    fn report_id(&self, response: &str) -> Result<String>;
+}
+
+pub fn submit(client: &dyn ExpenseClient, report: &ExpenseReport) -> Result<String> {
+    let request = client.request(report)?;
+    let boundary = format!("reimburser-{:x}", chrono::Utc::now().timestamp_micros());
+    let mut call = ureq::post(&request.url).set(
+        "Content-Type",
+        &format!("multipart/form-data; boundary={}", boundary),
+    );
+    for (name, value) in &request.headers {
+        call = call.set(name, value);
+    }
+    match call.send_bytes(&multipart(&request.parts, &boundary)) {
+        Ok(response) => client.report_id(&response.into_string()?),
+        Err(ureq::Error::Status(code, response)) => bail!(
+            "{} answered {}: {}",
+            request.url,
+            code,
+            response.into_string().unwrap_or_default()
+        ),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn multipart(parts: &[Part], boundary: &str) -> Vec<u8> {
+    let mut body: Vec<u8> = Vec::new();
+    for part in parts {
+        body.extend(format!("--{}\r\n", boundary).as_bytes());
+        match &part.file_name {
+            Some(file_name) => body.extend(
+                format!(
+                    "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n",
+                    part.name, file_name
+                )
+                .as_bytes(),
+            ),
+            None => body.extend(
+                format!("Content-Disposition: form-data; name=\"{}\"\r\n", part.name).as_bytes(),
+            ),
+        }
+        body.extend(format!("Content-Type: {}\r\n\r\n", part.content_type).as_bytes());
+        body.extend(&part.data);
+        body.extend(b"\r\n");
+    }
+    body.extend(format!("--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+// What a dry run prints. Files are only summarised and the token is masked.
+impl fmt::Display for ExpenseRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "POST {}", self.url)?;
+        for (name, value) in &self.headers {
+            if name.eq_ignore_ascii_case("Authorization") {
+                writeln!(f, "{}: <hidden>", name)?;
+            } else {
+                writeln!(f, "{}: {}", name, value)?;
+            }
+        }
+        for part in &self.parts {
+            match &part.file_name {
+                Some(file_name) => writeln!(
+                    f,
+                    "\n[{}] {}, {} ({} bytes)",
+                    part.name,
+                    file_name,
+                    part.content_type,
+                    part.data.len()
+                )?,
+                None => writeln!(
+                    f,
+                    "\n[{}] {}\n{}",
+                    part.name,
+                    part.content_type,
+                    String::from_utf8_lossy(&part.data)
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt::Write;
+
+use crate::claim::{Claim, format_date, format_vat};
+use crate::data::{Provider, Trip};
+use crate::expense::Category;
+
+// Inline, so the file can be sent around on its own
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin: 0.5em 0 1.5em; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.75em; text-align: left; }
+td.price, th.price { text-align: right; }
+tfoot td { font-weight: bold; }
+details { margin: 0.25em 0; }
+summary { cursor: pointer; }
+dt { font-weight: bold; float: left; width: 6em; }
+dd { margin-left: 6em; }";
+
+/// Self-contained HTML page with the claim, for sharing over chat and email
+pub fn html_report(claim: &Claim) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Commuting expenses claim</title>\n");
+    let _ = writeln!(html, "<style>\n{}\n</style>", STYLE);
+    html.push_str("</head>\n<body>\n<h1>Commuting expenses claim</h1>\n<dl>\n");
+
+    let mut field = |label: &str, value: String| {
+        let _ = writeln!(html, "<dt>{}</dt><dd>{}</dd>", label, escape(&value));
+    };
+    if let Some(employee) = &claim.employee {
+        field("Employee", employee.clone());
+    }
+    if let Some((start, end)) = claim.period() {
+        field("Period", format!("{} - {}", start, end));
+    }
+    field("Route", claim.route());
+    field("Invoice", claim.invoice_reference());
+    if let Some(card) = &claim.invoice.card_number {
+        field("Card", card.clone());
+    }
+    html.push_str("</dl>\n");
+
+    html.push_str("<h2>Trips</h2>\n");
+    trip_table(
+        &mut html,
+        claim.trips.iter(),
+        claim.category_total(Category::Commute),
+    );
+
+    html.push_str("<h2>Journeys</h2>\n");
+    // Numbered like the highlights in the annotated invoice
+    for (number, direction, legs) in claim.journeys() {
+        let first = legs[0];
+        let last = legs[legs.len() - 1];
+        let total: f32 = legs.iter().map(|t| t.total()).sum();
+        let _ = writeln!(
+            html,
+            "<details><summary>#{} {} {}: {} &rarr; {}, {} leg{}, {:.2}</summary>",
+            number,
+            first.date,
+            direction,
+            escape(&first.from),
+            escape(&last.to),
+            legs.len(),
+            if legs.len() == 1 { "" } else { "s" },
+            total
+        );
+        trip_table(&mut html, legs.into_iter(), total);
+        html.push_str("</details>\n");
+    }
+
+    if !claim.other.is_empty() {
+        html.push_str("<h2>Other expenses</h2>\n<table>\n");
+        html.push_str("<tr><th>Category</th><th>Date</th><th>Description</th><th class=\"price\">VAT</th><th class=\"price\">Amount</th></tr>\n");
+        for expense in &claim.other {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"price\">{}</td><td class=\"price\">{:.2}</td></tr>",
+                expense.category(),
+                expense.date(),
+                escape(&expense.description()),
+                format_vat(expense.as_ref()),
+                expense.amount()
+            );
+        }
+        let other_total: f32 = claim.other.iter().map(|expense| expense.amount()).sum();
+        let _ = writeln!(
+            html,
+            "<tfoot><tr><td colspan=\"4\">Total</td><td class=\"price\">{:.2}</td></tr></tfoot>\n</table>",
+            other_total
+        );
+    }
+
+    html.push_str(
+        "<h2>Subtotals</h2>\n<table>\n<tr><th>Subtotal</th><th class=\"price\">Total</th></tr>\n",
+    );
+    for provider in [Provider::NS, Provider::GVB] {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"price\">{:.2}</td></tr>",
+            provider,
+            claim.subtotal(provider.clone())
+        );
+    }
+    for category in claim.other_categories() {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"price\">{:.2}</td></tr>",
+            category,
+            claim.category_total(category)
+        );
+    }
+    html.push_str("</table>\n<table>\n<tr><th>Month</th><th class=\"price\">Total</th></tr>\n");
+    for (month, total) in claim.monthly_totals() {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"price\">{:.2}</td></tr>",
+            month, total
+        );
+    }
+    let _ = writeln!(
+        html,
+        "<tfoot><tr><td>Total</td><td class=\"price\">{:.2}</td></tr></tfoot>\n</table>",
+        claim.total()
+    );
+
+    html.push_str("<h2>Not claimed</h2>\n<table>\n");
+    html.push_str("<tr><th>Provider</th><th>Date</th><th>From</th><th>To</th><th class=\"price\">Price</th><th>Reason</th><th>Source</th></tr>\n");
+    for (trip, decision) in &claim.excluded {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"price\">{:.2}</td><td>{}</td><td>{}</td></tr>",
+            trip.provider,
+            escape(&format_date(trip)),
+            escape(&trip.from),
+            escape(&trip.to),
+            trip.total(),
+            decision,
+            escape(&format_source(trip))
+        );
+    }
+    let excluded_total: f32 = claim.excluded.iter().map(|(t, _)| t.total()).sum();
+    let _ = writeln!(
+        html,
+        "<tfoot><tr><td colspan=\"4\">Total</td><td class=\"price\">{:.2}</td><td colspan=\"2\"></td></tr></tfoot>\n</table>",
+        excluded_total
+    );
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn trip_table<'a>(html: &mut String, trips: impl Iterator<Item = &'a Trip>, total: f32) {
+    html.push_str("<table>\n<tr><th>Provider</th><th>Date</th><th>From</th><th>To</th><th class=\"price\">Price</th><th>Source</th></tr>\n");
+    for trip in trips {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"price\">{:.2}</td><td>{}</td></tr>",
+            trip.provider,
+            escape(&format_date(trip)),
+            escape(&trip.from),
+            escape(&trip.to),
+            trip.total(),
+            escape(&format_source(trip))
+        );
+    }
+    let _ = writeln!(
+        html,
+        "<tfoot><tr><td colspan=\"4\">Total</td><td class=\"price\">{:.2}</td><td></td></tr></tfoot>\n</table>",
+        total
+    );
+}
+
+// Page for PDFs, line for CSV exports, as a reader counts them
+fn format_source(trip: &Trip) -> String {
+    let Some(source) = &trip.source else {
+        return String::new();
+    };
+    let file = source
+        .file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match source.page {
+        Some(page) => format!("{} p. {}", file, page + 1),
+        None => format!("{} line {}", file, source.lines.start + 1),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Invoice;
+    use crate::trip_filter::Direction;
+    use chrono::NaiveDate;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_html_report() {
+        let trip = |day, provider, from: &str, to: &str, price| {
+            let date = NaiveDate::from_ymd_opt(2025, 3, day).unwrap();
+            let mut trip = Trip::new(date, provider, from.to_string(), to.to_string(), price);
+            trip.journey = Some((2, Direction::ToWork));
+            trip
+        };
+        let trips = vec![
+            trip(3, Provider::NS, "Hilversum", "Amsterdam Centraal", 5.6),
+            trip(3, Provider::GVB, "Centraal Station", "Rokin", 1.2),
+        ];
+        let claim = Claim {
+            employee: Some("J. Jansen".to_string()),
+            to: vec!["Rokin".to_string()],
+            ..Claim::for_test(
+                Invoice::new(PathBuf::from("invoice.pdf"), Vec::new(), Vec::new()),
+                trips,
+            )
+        };
+
+        let html = html_report(&claim);
+        assert!(html.contains("<dd>J. Jansen</dd>"));
+        assert!(html.contains("#2 2025-03-03 to work: Hilversum &rarr; Rokin, 2 legs, 6.80"));
+        assert!(html.contains("<tr><td>2025-03</td><td class=\"price\">6.80</td></tr>"));
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+
+use crate::claim::Claim;
+use crate::data::{Source, Trip};
+use crate::expense_client::cents;
+
+/// Claim as JSON for other tools, every trip with the place it was read from
+pub fn json_report(claim: &Claim) -> Result<String> {
+    let excluded: Vec<Value> = claim
+        .excluded
+        .iter()
+        .map(|(trip, decision)| {
+            let mut value = trip_json(trip);
+            value["reason"] = json!(decision.to_string());
+            value
+        })
+        .collect();
+    let other: Vec<Value> = claim
+        .other
+        .iter()
+        .map(|expense| {
+            json!({
+                "category": expense.category().to_string(),
+                "date": expense.date(),
+                "description": expense.description(),
+                "amount": cents(expense.amount()),
+                "file": expense.evidence(),
+            })
+        })
+        .collect();
+    let report = json!({
+        "employee": claim.employee,
+        "invoice": invoice_json(claim),
+        "route": claim.route(),
+        "period": claim.period(),
+        "trips": claim.trips.iter().map(trip_json).collect::<Vec<_>>(),
+        "other": other,
+        "excluded": excluded,
+        "total": cents(claim.total()),
+    });
+    Ok(serde_json::to_string_pretty(&report)?)
+}
+
+fn invoice_json(claim: &Claim) -> Value {
+    let invoice = &claim.invoice;
+    json!({
+        "reference": claim.invoice_reference(),
+        "number": invoice.number,
+        "date": invoice.date,
+        "period": invoice.period,
+        "customer_number": invoice.customer_number,
+        "card_number": invoice.card_number,
+        "file": invoice.file,
+    })
+}
+
+fn trip_json(trip: &Trip) -> Value {
+    json!({
+        "date": trip.date,
+        "check_in": trip.check_in,
+        "provider": trip.provider.to_string(),
+        "from": trip.from,
+        "to": trip.to,
+        "price": cents(trip.total()),
+        "source": trip.source.as_ref().map(source_json),
+    })
+}
+
+fn source_json(source: &Source) -> Value {
+    json!({
+        "file": source.file,
+        "page": source.page,
+        "lines": [source.lines.start, source.lines.end],
+        "bounds": source
+            .bounds
+            .iter()
+            .map(|(page, b)| json!({"page": page, "box": [b.left, b.bottom, b.right, b.top]}))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Bounds, Invoice, Provider};
+    use chrono::NaiveDate;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_json_report_sources() {
+        let date = NaiveDate::from_ymd_opt(2025, 3, 3).unwrap();
+        let mut source = Source::new(4..6);
+        source.page = Some(1);
+        source.bounds = vec![(
+            1,
+            Bounds {
+                left: 40.0,
+                bottom: 500.0,
+                right: 550.0,
+                top: 520.0,
+            },
+        )];
+        let trip = Trip::new(
+            date,
+            Provider::NS,
+            "Hilversum".to_string(),
+            "Amsterdam Centraal".to_string(),
+            5.6,
+        )
+        .with_source(source);
+        let mut invoice = Invoice::new(PathBuf::from("invoice.pdf"), vec![trip], Vec::new());
+        invoice.number = Some("NS-123".to_string());
+        invoice.customer_number = Some("1234567".to_string());
+        let trips = invoice.ns_trips.clone();
+        let claim = Claim::for_test(invoice, trips);
+
+        let report: Value = serde_json::from_str(&json_report(&claim).unwrap()).unwrap();
+        assert_eq!(report["invoice"]["reference"], "NS-123");
+        assert_eq!(report["invoice"]["customer_number"], "1234567");
+        assert_eq!(report["invoice"]["card_number"], Value::Null);
+        let source = &report["trips"][0]["source"];
+        assert_eq!(source["file"], "invoice.pdf");
+        assert_eq!(source["page"], 1);
+        assert_eq!(source["lines"], json!([4, 6]));
+        assert_eq!(report["trips"][0]["price"], 5.6);
+        assert_eq!(
+            source["bounds"],
+            json!([{"page": 1, "box": [40.0, 500.0, 550.0, 520.0]}])
+        );
+    }
+}
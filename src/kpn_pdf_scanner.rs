@@ -0,0 +1,166 @@
+use std::path::PathBuf;
+
+use crate::data::TelecomBill;
+use anyhow::{Context, Result};
+
+use chrono::NaiveDate;
+use regex::Regex;
+
+use pdfium_render::prelude::*;
+
+// KPN bills from https://mijn.kpn.com, one subscription period per bill
+pub fn kpn_pdf_scanner(pdf: PathBuf) -> Result<TelecomBill> {
+    let pdfium = Pdfium::default();
+
+    let doc = pdfium.load_pdf_from_file(&pdf, None)?;
+
+    let mut lines: Vec<String> = Vec::new();
+    for page in doc.pages().iter() {
+        if let Ok(text) = page.text() {
+            lines.extend(text.all().split("\n").map(|line| line.to_string()));
+        }
+    }
+
+    scan_bill(pdf, &lines)
+}
+
+fn scan_bill(pdf: PathBuf, lines: &[String]) -> Result<TelecomBill> {
+    // Dates are written out ("1 maart 2025") on the bill itself and numeric in the overview
+    let date = r"\d{1,2}[-\s]\w+[-\s]\d{4}";
+    let amount = r"-?\d{1,3}(?:\.\d{3})*,\d{2}";
+    let re_number = Regex::new(r"Factuurnummer:?\s+(?P<value>\S+)")?;
+    let re_date = Regex::new(&format!(r"Factuurdatum:?\s+(?P<value>{date})"))?;
+    let re_period = Regex::new(&format!(
+        r"(?:Periode|Factuurperiode|Abonnementsperiode):?\s+(?P<start>{date})\s+(?:t/m|tot en met|-)\s+(?P<end>{date})"
+    ))?;
+    let re_amount = Regex::new(&format!(
+        r"(?i:Totaal incl\.? btw|Te betalen|Totaalbedrag):?\s+€?\s*(?P<value>{amount})"
+    ))?;
+    // The VAT line may also show the amount it was taken over, the VAT itself comes last
+    let re_vat = Regex::new(&format!(
+        r"(?i:btw)\s+(?P<rate>\d+(?:,\d+)?)\s*%.*?(?P<value>{amount})\s*$"
+    ))?;
+
+    let (mut number, mut bill_date, mut period, mut total, mut vat) =
+        (None, None, None, None, None);
+    // Only the first occurrence counts, the payment slip repeats the total
+    for line in lines {
+        if number.is_none() {
+            number = re_number.captures(line).map(|cap| cap["value"].to_string());
+        }
+        if bill_date.is_none() {
+            bill_date = re_date
+                .captures(line)
+                .and_then(|cap| parse_date(&cap["value"]));
+        }
+        if period.is_none() {
+            period = re_period
+                .captures(line)
+                .and_then(|cap| Some((parse_date(&cap["start"])?, parse_date(&cap["end"])?)));
+        }
+        if total.is_none() {
+            total = re_amount
+                .captures(line)
+                .map(|cap| parse_amount(&cap["value"]));
+        }
+        if vat.is_none() {
+            vat = re_vat
+                .captures(line)
+                .map(|cap| (parse_amount(&cap["value"]), parse_amount(&cap["rate"])));
+        }
+    }
+
+    Ok(TelecomBill {
+        provider: "KPN".to_string(),
+        number,
+        date: bill_date,
+        period: period.with_context(|| format!("No billing period in {}", pdf.display()))?,
+        amount: total.with_context(|| format!("No total amount in {}", pdf.display()))?,
+        vat,
+        file: pdf,
+    })
+}
+
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%d-%m-%Y") {
+        return Some(date);
+    }
+    const MONTHS: [&str; 12] = [
+        "januari",
+        "februari",
+        "maart",
+        "april",
+        "mei",
+        "juni",
+        "juli",
+        "augustus",
+        "september",
+        "oktober",
+        "november",
+        "december",
+    ];
+    let mut parts = s.split_whitespace();
+    let day = parts.next()?.parse().ok()?;
+    let month = parts.next()?.to_lowercase();
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    let year = parts.next()?.parse().ok()?;
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+// KPN only bills in Dutch: 1.234,56
+fn parse_amount(s: &str) -> f32 {
+    s.replace(".", "")
+        .replace(",", ".")
+        .parse()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|line| line.trim().to_string()).collect()
+    }
+
+    #[test]
+    fn test_scan_kpn_bill() {
+        let bill = lines(
+            "KPN B.V.
+             Factuurnummer: 1234567890
+             Factuurdatum: 12 maart 2025
+             Periode 1 maart 2025 t/m 31 maart 2025
+             KPN Internet 100 Mbit € 41,32
+             Totaal excl. btw € 41,32
+             Btw 21% over € 41,32 € 8,68
+             Totaal incl. btw € 50,00
+             Te betalen € 50,00",
+        );
+        let bill = scan_bill(PathBuf::from("kpn.pdf"), &bill).unwrap();
+        assert_eq!(bill.number.as_deref(), Some("1234567890"));
+        assert_eq!(bill.date, NaiveDate::from_ymd_opt(2025, 3, 12));
+        assert_eq!(
+            bill.period,
+            (
+                NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2025, 3, 31).unwrap()
+            )
+        );
+        assert_eq!(bill.amount, 50.0);
+        assert_eq!(bill.vat, Some((8.68, 21.0)));
+    }
+
+    #[test]
+    fn test_scan_bill_without_total() {
+        let bill = lines("Factuurnummer: 1234567890\nPeriode 01-03-2025 t/m 31-03-2025");
+        assert!(scan_bill(PathBuf::from("kpn.pdf"), &bill).is_err());
+    }
+
+    #[test]
+    fn test_scan_bill_without_vat() {
+        let bill = lines("Periode 01-03-2025 t/m 31-03-2025\nTe betalen € 50,00");
+        let bill = scan_bill(PathBuf::from("kpn.pdf"), &bill).unwrap();
+        assert_eq!(bill.amount, 50.0);
+        assert_eq!(bill.vat, None);
+    }
+}
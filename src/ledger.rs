@@ -0,0 +1,388 @@
+use std::path::Path;
+
+use std::fmt;
+
+use anyhow::{Context, Result, bail};
+use chrono::{Local, NaiveDate};
+use clap::ValueEnum;
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::claim::Claim;
+use crate::data::Trip;
+
+// Each entry upgrades the schema by one version, PRAGMA user_version tracks how far a file is
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE claims (
+        id INTEGER PRIMARY KEY,
+        created TEXT NOT NULL,
+        invoice TEXT NOT NULL,
+        period_start TEXT,
+        period_end TEXT,
+        total REAL NOT NULL
+    );
+    CREATE TABLE trips (
+        date TEXT NOT NULL,
+        provider TEXT NOT NULL,
+        origin TEXT NOT NULL,
+        destination TEXT NOT NULL,
+        price_cents INTEGER NOT NULL,
+        invoice_number TEXT NOT NULL,
+        occurrence INTEGER NOT NULL,
+        claim_id INTEGER NOT NULL REFERENCES claims(id),
+        PRIMARY KEY (date, provider, origin, destination, price_cents, invoice_number, occurrence)
+    );",
+    "ALTER TABLE claims ADD COLUMN status TEXT NOT NULL DEFAULT 'draft';
+    ALTER TABLE claims ADD COLUMN reference TEXT;
+    CREATE TABLE status_changes (
+        claim_id INTEGER NOT NULL REFERENCES claims(id),
+        status TEXT NOT NULL,
+        changed TEXT NOT NULL
+    );",
+];
+
+/// Trips claimed in earlier runs, kept in a SQLite file so the same trip isn't claimed twice
+pub struct Ledger {
+    connection: Connection,
+}
+
+/// Where a claim is in the reimbursement process, it only moves forward
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Draft,
+    Submitted,
+    Approved,
+    Paid,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Draft => "draft",
+            Status::Submitted => "submitted",
+            Status::Approved => "approved",
+            Status::Paid => "paid",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Status> {
+        [
+            Status::Draft,
+            Status::Submitted,
+            Status::Approved,
+            Status::Paid,
+        ]
+        .into_iter()
+        .find(|status| status.name() == name)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A claim as it was recorded, with its latest status
+#[derive(Debug)]
+pub struct ClaimRecord {
+    pub id: i64,
+    pub created: NaiveDate,
+    pub invoice: String,
+    pub period: Option<(NaiveDate, NaiveDate)>,
+    pub total: f32,
+    pub status: Status,
+    pub changed: NaiveDate, // Day of the last status change, or the day it was recorded
+    pub reference: Option<String>, // Id at the expense service
+}
+
+/// Identity of a trip that stays the same when the invoice is read again. Identical trips on
+/// one invoice, e.g. two rides between the same stations on a day, are told apart by their
+/// occurrence, counted over the whole invoice when it was read.
+#[derive(Debug, PartialEq)]
+struct TripKey {
+    date: NaiveDate,
+    provider: String,
+    from: String,
+    to: String,
+    price_cents: i64,
+    invoice_number: String,
+    occurrence: usize,
+}
+
+impl TripKey {
+    // Exports without an invoice number share an empty one, so overlapping exports still match
+    fn new(trip: &Trip, invoice_number: Option<&str>) -> TripKey {
+        TripKey {
+            date: trip.date,
+            provider: trip.provider.to_string(),
+            from: trip.from.clone(),
+            to: trip.to.clone(),
+            price_cents: trip.price_cents(),
+            invoice_number: invoice_number.unwrap_or_default().to_string(),
+            occurrence: trip.source.as_ref().map_or(0, |source| source.occurrence),
+        }
+    }
+}
+
+impl Ledger {
+    pub fn open(path: &Path) -> Result<Ledger> {
+        let mut connection = Connection::open(path)?;
+        let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", index + 1)?;
+            transaction.commit()?;
+        }
+        Ok(Ledger { connection })
+    }
+
+    /// Claim and day each trip was claimed before, if it was
+    pub fn claimed(
+        &self,
+        trips: &[Trip],
+        invoice_number: Option<&str>,
+    ) -> Result<Vec<Option<(i64, NaiveDate)>>> {
+        let mut statement = self.connection.prepare(
+            "SELECT claims.id, claims.created FROM trips JOIN claims ON claims.id = trips.claim_id
+             WHERE date = ?1 AND provider = ?2 AND origin = ?3 AND destination = ?4
+               AND price_cents = ?5 AND invoice_number = ?6 AND occurrence = ?7",
+        )?;
+        trips
+            .iter()
+            .map(|trip| {
+                let key = TripKey::new(trip, invoice_number);
+                Ok(statement
+                    .query_row(
+                        params![
+                            key.date,
+                            key.provider,
+                            key.from,
+                            key.to,
+                            key.price_cents,
+                            key.invoice_number,
+                            key.occurrence
+                        ],
+                        |row| Ok((row.get(0)?, row.get::<_, NaiveDate>(1)?)),
+                    )
+                    .optional()?)
+            })
+            .collect()
+    }
+
+    /// Stores the claim with its trips, returns its id
+    pub fn record(&mut self, claim: &Claim) -> Result<i64> {
+        let transaction = self.connection.transaction()?;
+        let (start, end) = claim.period().unzip();
+        transaction.execute(
+            "INSERT INTO claims (created, invoice, period_start, period_end, total)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                Local::now().date_naive(),
+                claim.invoice_reference(),
+                start,
+                end,
+                claim.total()
+            ],
+        )?;
+        let id = transaction.last_insert_rowid();
+        for trip in &claim.trips {
+            let key = TripKey::new(trip, claim.invoice.number.as_deref());
+            transaction.execute(
+                "INSERT INTO trips VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    key.date,
+                    key.provider,
+                    key.from,
+                    key.to,
+                    key.price_cents,
+                    key.invoice_number,
+                    key.occurrence,
+                    id
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(id)
+    }
+
+    pub fn claims(&self) -> Result<Vec<ClaimRecord>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, created, invoice, period_start, period_end, total, status, reference,
+                 (SELECT MAX(changed) FROM status_changes WHERE claim_id = claims.id)
+             FROM claims ORDER BY id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            let created: NaiveDate = row.get(1)?;
+            let start: Option<NaiveDate> = row.get(3)?;
+            let end: Option<NaiveDate> = row.get(4)?;
+            let changed: Option<NaiveDate> = row.get(8)?;
+            Ok((
+                ClaimRecord {
+                    id: row.get(0)?,
+                    created,
+                    invoice: row.get(2)?,
+                    period: start.zip(end),
+                    total: row.get(5)?,
+                    status: Status::Draft,
+                    changed: changed.unwrap_or(created),
+                    reference: row.get(7)?,
+                },
+                row.get::<_, String>(6)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (mut record, status) = row?;
+            record.status = Status::from_name(&status)
+                .with_context(|| format!("Unknown status {} of claim {}", status, record.id))?;
+            Ok(record)
+        })
+        .collect()
+    }
+
+    /// Moves the claim on to the given status. Going back would lose track of what was paid.
+    pub fn set_status(&mut self, id: i64, status: Status, reference: Option<&str>) -> Result<()> {
+        let current = self
+            .claims()?
+            .into_iter()
+            .find(|record| record.id == id)
+            .with_context(|| format!("There is no claim {} in the ledger", id))?
+            .status;
+        if status <= current {
+            bail!(
+                "Claim {} is {} already, it can't become {}",
+                id,
+                current,
+                status
+            );
+        }
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "UPDATE claims SET status = ?1, reference = COALESCE(?2, reference) WHERE id = ?3",
+            params![status.name(), reference, id],
+        )?;
+        transaction.execute(
+            "INSERT INTO status_changes VALUES (?1, ?2, ?3)",
+            params![id, status.name(), Local::now().date_naive()],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// Amount per status of the claims that aren't paid yet
+pub fn outstanding(claims: &[ClaimRecord]) -> Vec<(Status, usize, f32)> {
+    [Status::Draft, Status::Submitted, Status::Approved]
+        .into_iter()
+        .map(|status| {
+            let open = claims.iter().filter(|record| record.status == status);
+            (
+                status,
+                open.clone().count(),
+                // An empty f32 sum is -0.0
+                open.fold(0.0, |sum, record| sum + record.total),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Invoice, Provider};
+    use std::path::PathBuf;
+
+    fn trip(day: u32, from: &str, to: &str) -> Trip {
+        let date = NaiveDate::from_ymd_opt(2025, 3, day).unwrap();
+        Trip::new(date, Provider::NS, from.to_string(), to.to_string(), 5.6)
+    }
+
+    fn claim(trips: Vec<Trip>) -> Claim {
+        let mut invoice = Invoice::new(PathBuf::from("invoice.pdf"), Vec::new(), Vec::new());
+        invoice.number = Some("NS-123".to_string());
+        Claim::for_test(invoice, trips)
+    }
+
+    #[test]
+    fn test_claimed_trips_are_found_again() {
+        let mut ledger = Ledger::open(Path::new(":memory:")).unwrap();
+        let first = claim(vec![
+            trip(3, "Hilversum", "Amsterdam Centraal"),
+            trip(3, "Amsterdam Centraal", "Hilversum"),
+        ]);
+        let id = ledger.record(&first).unwrap();
+
+        // The same invoice again, now with a later trip
+        let trips = vec![
+            trip(3, "Hilversum", "Amsterdam Centraal"),
+            trip(3, "Amsterdam Centraal", "Hilversum"),
+            trip(4, "Hilversum", "Amsterdam Centraal"),
+        ];
+        let claimed: Vec<Option<i64>> = ledger
+            .claimed(&trips, Some("NS-123"))
+            .unwrap()
+            .into_iter()
+            .map(|before| before.map(|(claim, _)| claim))
+            .collect();
+        assert_eq!(claimed, vec![Some(id), Some(id), None]);
+        // Another invoice is another trip
+        assert_eq!(
+            ledger.claimed(&trips[..1], Some("NS-456")).unwrap(),
+            vec![None]
+        );
+    }
+
+    #[test]
+    fn test_status_moves_forward() {
+        let mut ledger = Ledger::open(Path::new(":memory:")).unwrap();
+        let first = ledger.record(&claim(vec![trip(3, "Hilversum", "Amsterdam Centraal")]));
+        let second = ledger.record(&claim(vec![trip(4, "Hilversum", "Amsterdam Centraal")]));
+        let (first, second) = (first.unwrap(), second.unwrap());
+
+        ledger
+            .set_status(first, Status::Submitted, Some("rpt_42"))
+            .unwrap();
+        ledger.set_status(first, Status::Paid, None).unwrap();
+        assert!(ledger.set_status(first, Status::Approved, None).is_err());
+        assert!(ledger.set_status(99, Status::Paid, None).is_err());
+
+        let claims = ledger.claims().unwrap();
+        assert_eq!(claims[0].status, Status::Paid);
+        assert_eq!(claims[0].reference.as_deref(), Some("rpt_42"));
+        assert_eq!(claims[1].id, second);
+        assert_eq!(claims[1].status, Status::Draft);
+        assert_eq!(
+            outstanding(&claims),
+            vec![
+                (Status::Draft, 1, 5.6),
+                (Status::Submitted, 0, 0.0),
+                (Status::Approved, 0, 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identical_trips_have_their_own_key() {
+        let invoice = Invoice::new(
+            PathBuf::from("invoice.pdf"),
+            vec![
+                trip(3, "Hilversum", "Amsterdam Centraal"),
+                trip(3, "Hilversum", "Amsterdam Centraal"),
+            ],
+            Vec::new(),
+        );
+        let trips = invoice.ns_trips;
+        let mut ledger = Ledger::open(Path::new(":memory:")).unwrap();
+
+        // Only the second ride was claimed the first time
+        ledger.record(&claim(trips[1..].to_vec())).unwrap();
+        let claimed = ledger.claimed(&trips, Some("NS-123")).unwrap();
+        assert!(claimed[0].is_none());
+        assert!(claimed[1].is_some());
+
+        // The next run on the same invoice claims the one that is left
+        ledger.record(&claim(trips[..1].to_vec())).unwrap();
+        let claimed = ledger.claimed(&trips, Some("NS-123")).unwrap();
+        assert!(claimed.iter().all(|before| before.is_some()));
+    }
+}
@@ -1,22 +1,117 @@
 use std::path::PathBuf;
 
+use crate::claim::{Claim, format_date, format_price, format_vat};
+use crate::csv_report::csv_report;
+use crate::evidence_pdf::{annotated_invoice, redacted_invoice};
+use crate::expense::Expense;
+use crate::expense_client::{ExpenseClient, ExpenseReport, submit};
+use crate::html_report::html_report;
+use crate::json_report::json_report;
+use crate::kpn_pdf_scanner::kpn_pdf_scanner;
+use crate::ledger::{Ledger, Status, outstanding};
+use crate::navan_client::NavanClient;
 use crate::ns_pdf_scanner::ns_pdf_scanner;
-use clap::Parser;
-use data::Trip;
+use crate::ov_csv_scanner::ov_csv_scanner;
+use crate::pdf_report::pdf_report;
+use crate::xlsx_report::xlsx_report;
+use chrono::{Duration, NaiveDate};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use data::{Invoice, Provider, Trip};
 use prettytable::{Table, row};
-use trip_filter::{trip_station_filter, trip_workday_filter};
+use trip_filter::{
+    Decision, Direction, trip_claim_split, trip_decisions, trip_missing_direction, trip_time_sort,
+    trip_unbalanced_days,
+};
 
+mod claim;
+mod csv_report;
 mod data;
+mod evidence_pdf;
+mod expense;
+mod expense_client;
+mod html_report;
+mod json_report;
+mod kpn_pdf_scanner;
+mod ledger;
+mod navan_client;
 mod ns_pdf_scanner;
+mod ov_csv_scanner;
+mod pdf_report;
 mod trip_filter;
+mod xlsx_report;
+
+#[derive(ValueEnum, Clone, Debug)]
+enum Format {
+    /// Print the claim to the terminal
+    Table,
+    /// Claim document to sign and hand in
+    Pdf,
+    /// Single page with journeys and monthly totals, to share over chat and email
+    Html,
+    /// Workbook with the trips, totals per provider and month, and the excluded trips
+    Xlsx,
+    /// Claimed and excluded trips with where each was found in the source file
+    Json,
+    /// Same as json, one row per trip
+    Csv,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum Service {
+    Navan,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Submit the claim as an expense report, with the invoice attached. The claim options
+    /// go before the subcommand: reimburser -f invoice.pdf --from-ns A --to-ns B submit ...
+    Submit {
+        /// Expense-management service to submit to
+        #[arg(long, value_enum, default_value_t = Service::Navan)]
+        service: Service,
+        /// Base URL of the service's API
+        #[arg(long, env = "REIMBURSER_ENDPOINT")]
+        endpoint: String,
+        /// API token of the service
+        #[arg(long, env = "REIMBURSER_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        /// Print the request instead of sending it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Claims recorded in the ledger with their status. Needs --ledger, no invoice:
+    /// reimburser --ledger claims.db claims mark 3 paid
+    Claims {
+        #[command(subcommand)]
+        action: Option<ClaimsAction>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ClaimsAction {
+    /// List the claims with their period, amount and status (default)
+    List,
+    /// Move a claim on to the next status, e.g. when finance approved or paid it
+    Mark {
+        /// Claim id, as listed
+        id: i64,
+        #[arg(value_enum)]
+        status: Status,
+    },
+    /// Amounts of the claims that aren't paid yet
+    Outstanding,
+}
 
 /// Simple
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     /// Invoice from NS in PDF. Can be downloaded from: https://www.ns.nl/mijnns#/betaaloverzicht
+    /// A transaction overview in CSV from https://www.ov-chipkaart.nl works too and includes
+    /// check-in and check-out times.
+    /// Not needed for the claims subcommand.
     #[arg(short = 'f', long)]
-    input: String,
+    input: Option<String>,
     /// List of train stations you depart from. Multiple stations can be specified using
     /// multiple argumets. Example --from-ns A --from-ns B
     #[arg(long)]
@@ -27,56 +122,527 @@ struct Args {
     to_ns: Vec<String>,
     /// Optional. List of GVB stations you depart from. Multiple stations can be specified using
     /// multiple argumets. Example --from-gvb A --from-gvb B
+    /// Not needed for GVB legs that continue a train journey, e.g. Hilversum -> Amsterdam
+    /// Centraal -> Rokin only needs --from-ns Hilversum --to-gvb Rokin
     #[arg(long)]
-    from_gvb: Option<Vec<String>>,
+    from_gvb: Vec<String>,
     /// Optional. List of GVB stations you arrive to. Multiple stations can be specified using
     /// multiple argumets. Example --to-gvb A --to-gvb B
     #[arg(long)]
-    to_gvb: Option<Vec<String>>,
+    to_gvb: Vec<String>,
+    /// Claim journeys without a check-out too. By default they are only listed, so the
+    /// boarding fare can be reclaimed from the operator instead.
+    #[arg(long)]
+    include_incomplete: bool,
+    /// Maximum time in minutes between two legs of one journey. Only applies when the
+    /// input has check-in and check-out times.
+    #[arg(long, default_value_t = 60)]
+    max_transfer_gap: i64,
+    /// Print every parsed trip with the reason it was claimed or left out
+    #[arg(long)]
+    explain: bool,
+    /// Also list the trips that were not claimed, with their total
+    #[arg(long)]
+    show_excluded: bool,
+    /// Claim the missing direction of a day that only has a journey one way, e.g. when the
+    /// check-out was missed and the operator refunded the trip. Format: YYYY-MM-DD
+    #[arg(long)]
+    claim_missing: Vec<NaiveDate>,
+    /// Fail instead of warning when the invoice totals don't match the parsed trips
+    #[arg(long)]
+    strict: bool,
+    /// How to output the claim
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// File to write the claim document to. Defaults to claim.<format>
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+    /// Name printed on the claim document
+    #[arg(long)]
+    employee: Option<String>,
+    /// KPN bill in PDF to claim as internet allowance in the same report. Multiple bills
+    /// can be specified using multiple arguments. Example --kpn march.pdf --kpn april.pdf
+    #[arg(long)]
+    kpn: Vec<PathBuf>,
+    /// Write a copy of the PDF invoice to this file with the claimed rows highlighted and
+    /// numbered by journey
+    #[arg(long)]
+    annotate: Option<PathBuf>,
+    /// Write a copy of the PDF invoice to this file without the trips that weren't claimed.
+    /// When submitting, this copy is attached instead of the invoice.
+    #[arg(long)]
+    redact: Option<PathBuf>,
+    /// SQLite file with the trips claimed before. Trips found in it are left out of the claim.
+    #[arg(long, env = "REIMBURSER_LEDGER")]
+    ledger: Option<PathBuf>,
+    /// Record the claimed trips in the ledger, so a later run doesn't claim them again
+    #[arg(long, requires = "ledger")]
+    record: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
 fn main() {
     let args = Args::parse();
-    let trips = ns_pdf_scanner(PathBuf::from(args.input)).unwrap();
+    if let Some(Command::Claims { action }) = &args.command {
+        let Some(path) = &args.ledger else {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "the claims are kept in the ledger, use --ledger or REIMBURSER_LEDGER",
+                )
+                .exit();
+        };
+        // Opening would create an empty ledger, a typo in the path would go unnoticed
+        if !path.exists() {
+            eprintln!("There is no ledger at {}", path.display());
+            std::process::exit(1);
+        }
+        let mut ledger = Ledger::open(path).unwrap();
+        match action.as_ref().unwrap_or(&ClaimsAction::List) {
+            ClaimsAction::List => print_claims(&ledger),
+            ClaimsAction::Mark { id, status } => {
+                if let Err(error) = ledger.set_status(*id, *status, None) {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+                println!("Claim {} is {} now", id, status);
+            }
+            ClaimsAction::Outstanding => print_outstanding(&ledger),
+        }
+        return;
+    }
+    let Some(input) = args.input.map(PathBuf::from) else {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "an invoice is needed, use --input",
+            )
+            .exit();
+    };
+    let invoice = if input.extension().is_some_and(|ext| ext == "csv") {
+        ov_csv_scanner(input).unwrap()
+    } else {
+        ns_pdf_scanner(input).unwrap()
+    };
+    // A row the scanner couldn't read shows up as a difference with the invoice's own totals
+    let differences = invoice
+        .totals
+        .differences(&invoice.ns_trips, &invoice.gvb_trips);
+    for (label, printed, parsed) in &differences {
+        eprintln!(
+            "Warning: {} on the invoice is {:.2}, parsed trips add up to {:.2} (difference {:.2})",
+            label,
+            printed,
+            parsed,
+            printed - parsed
+        );
+    }
+    for ride in &invoice.skipped {
+        eprintln!(
+            "Warning: {} is not an NS or GVB ride and is left out of the claim",
+            ride
+        );
+    }
+    if args.strict && !differences.is_empty() {
+        eprintln!("Invoice totals don't match the parsed trips, refusing to continue");
+        std::process::exit(1);
+    }
+    // One list for all operators, so a train leg followed by a tram leg is one journey
+    let mut all_trips = invoice.ns_trips.clone();
+    all_trips.extend(invoice.gvb_trips.iter().cloned());
+    trip_time_sort(&mut all_trips);
+    let max_transfer_gap = Duration::minutes(args.max_transfer_gap);
+    let from: Vec<String> = args.from_ns.into_iter().chain(args.from_gvb).collect();
+    let to: Vec<String> = args.to_ns.into_iter().chain(args.to_gvb).collect();
+
+    print_invoice_header(&invoice);
+
+    let mut decisions = trip_decisions(
+        &all_trips,
+        &from,
+        &to,
+        max_transfer_gap,
+        args.include_incomplete,
+    );
+
+    // Legs added with --claim-missing are decisions too, so --explain lists them
+    let unbalanced_days = trip_unbalanced_days(&all_trips, &decisions);
+    for date in &args.claim_missing {
+        match unbalanced_days.iter().find(|(day, _)| day == date) {
+            Some((day, missing)) => {
+                let legs = trip_missing_direction(&all_trips, &decisions, *day, *missing);
+                decisions.extend(legs.iter().map(|_| Decision::Missing {
+                    direction: *missing,
+                }));
+                all_trips.extend(legs);
+            }
+            None => eprintln!(
+                "{} doesn't miss a commute direction, nothing to claim",
+                date
+            ),
+        }
+    }
+
+    let mut ledger = args
+        .ledger
+        .as_deref()
+        .map(|path| Ledger::open(path).unwrap());
+    if let Some(ledger) = &ledger {
+        let claimed_before = ledger
+            .claimed(&all_trips, invoice.number.as_deref())
+            .unwrap();
+        let mut count = 0;
+        for (decision, before) in decisions.iter_mut().zip(claimed_before) {
+            if let Some((claim, on)) = before
+                && decision.is_claimed()
+            {
+                *decision = Decision::AlreadyClaimed { claim, on };
+                count += 1;
+            }
+        }
+        if count > 0 {
+            eprintln!(
+                "Warning: {} trip(s) were claimed before and are left out, see --show-excluded",
+                count
+            );
+        }
+    }
+
+    if args.explain {
+        let mut table = Table::new();
+        table.add_row(row![
+            "Leg", "Provider", "Date", "From", "To", "Price", "Decision"
+        ]);
+        for (index, (trip, decision)) in all_trips.iter().zip(&decisions).enumerate() {
+            table.add_row(row![
+                index + 1,
+                trip.provider,
+                format_date(trip),
+                trip.from,
+                trip.to,
+                format_price(trip),
+                decision
+            ]);
+        }
+        table.printstd();
+        println!();
+    }
+
+    let (claimed_trips, excluded_trips) = trip_claim_split(all_trips, decisions);
 
-    let (mut filtered_ns_trips, mut filtered_gvb_trips) = (Vec::<Trip>::new(), Vec::<Trip>::new());
-    filtered_ns_trips = trip_workday_filter(trip_station_filter(trips.0, args.from_ns, args.to_ns));
-    if args.from_gvb.is_some() && args.to_gvb.is_some() {
-        filtered_gvb_trips = trip_workday_filter(trip_station_filter(
-            trips.1,
-            args.from_gvb.unwrap(),
-            args.to_gvb.unwrap(),
-        ));
+    let claim = Claim {
+        invoice,
+        employee: args.employee,
+        from,
+        to,
+        trips: claimed_trips,
+        excluded: excluded_trips,
+        other: args
+            .kpn
+            .into_iter()
+            .map(|bill| Box::new(kpn_pdf_scanner(bill).unwrap()) as Box<dyn Expense>)
+            .collect(),
+    };
+    for trip in claim.trips.iter().filter(|trip| trip.is_suspect()) {
+        eprintln!(
+            "Warning: {} {} {} - {} costs exactly the boarding fare of {:.2}, check that the check-out was registered",
+            trip.provider, trip.date, trip.from, trip.to, trip.price
+        );
     }
+    match args.format {
+        Format::Table => print_claim(&claim),
+        Format::Pdf => {
+            let output = args.output.unwrap_or_else(|| PathBuf::from("claim.pdf"));
+            pdf_report(&claim, &output).unwrap();
+            println!("Claim written to {}", output.display());
+        }
+        Format::Html => {
+            let output = args.output.unwrap_or_else(|| PathBuf::from("claim.html"));
+            std::fs::write(&output, html_report(&claim)).unwrap();
+            println!("Claim written to {}", output.display());
+        }
+        Format::Xlsx => {
+            let output = args.output.unwrap_or_else(|| PathBuf::from("claim.xlsx"));
+            xlsx_report(&claim, &output).unwrap();
+            println!("Claim written to {}", output.display());
+        }
+        Format::Json => {
+            let output = args.output.unwrap_or_else(|| PathBuf::from("claim.json"));
+            std::fs::write(&output, json_report(&claim).unwrap()).unwrap();
+            println!("Claim written to {}", output.display());
+        }
+        Format::Csv => {
+            let output = args.output.unwrap_or_else(|| PathBuf::from("claim.csv"));
+            csv_report(&claim, &output).unwrap();
+            println!("Claim written to {}", output.display());
+        }
+    }
+
+    if let Some(output) = &args.annotate {
+        annotated_invoice(&claim, output).unwrap();
+        println!("Annotated invoice written to {}", output.display());
+    }
+    if let Some(output) = &args.redact {
+        redacted_invoice(&claim, output).unwrap();
+        println!("Redacted invoice written to {}", output.display());
+    }
+
+    let mut submitted: Option<String> = None;
+    if let Some(Command::Submit {
+        service,
+        endpoint,
+        token,
+        dry_run,
+    }) = args.command
+    {
+        if token.is_none() && !dry_run {
+            eprintln!("An API token is needed to submit, use --token or REIMBURSER_TOKEN");
+            std::process::exit(1);
+        }
+        let token = token.unwrap_or_default();
+        let client: Box<dyn ExpenseClient> = match service {
+            Service::Navan => Box::new(NavanClient::new(&endpoint, &token)),
+        };
+        let mut report = ExpenseReport::from_claim(&claim).unwrap();
+        if let Some(redacted) = &args.redact {
+            report
+                .replace_attachment(&claim.invoice.file, redacted)
+                .unwrap();
+        }
+        if dry_run {
+            println!("\n{}", client.request(&report).unwrap());
+        } else {
+            let id = submit(client.as_ref(), &report).unwrap();
+            println!("\nSubmitted as expense report {}", id);
+            submitted = Some(id);
+        }
+    }
+
+    if let Some(ledger) = &mut ledger
+        && args.record
+    {
+        if claim.expenses().next().is_none() {
+            println!("\nNothing claimed, nothing recorded in the ledger");
+        } else {
+            let id = ledger.record(&claim).unwrap();
+            if let Some(reference) = &submitted {
+                ledger
+                    .set_status(id, Status::Submitted, Some(reference))
+                    .unwrap();
+            }
+            println!("\nRecorded as claim {} in the ledger", id);
+        }
+    }
+
+    if args.show_excluded {
+        println!("\nNot claimed:");
+        let mut table = Table::new();
+        table.add_row(row!["Provider", "Date", "From", "To", "Price", "Reason"]);
+        for (trip, decision) in &claim.excluded {
+            table.add_row(row![
+                trip.provider,
+                format_date(trip),
+                trip.from,
+                trip.to,
+                format_price(trip),
+                decision
+            ]);
+        }
+        table.printstd();
+        let excluded_total: f32 = claim.excluded.iter().map(|(t, _)| t.total()).sum();
+        println!("\nNot claimed total: {:.2}", excluded_total);
+    }
+
+    let unbalanced_days: Vec<&(NaiveDate, Direction)> = unbalanced_days
+        .iter()
+        .filter(|(day, _)| !args.claim_missing.contains(day))
+        .collect();
+    if !unbalanced_days.is_empty() {
+        println!("\nDays with only one commute direction. A leg may not have been recognised");
+        println!("or a check-out was missed. Claim the other direction with --claim-missing:");
+        let mut table = Table::new();
+        table.add_row(row!["Date", "Missing"]);
+        for (day, missing) in unbalanced_days {
+            table.add_row(row![day, missing]);
+        }
+        table.printstd();
+    }
+
+    // Already refunded ones don't need any action
+    let incomplete_trips: Vec<&Trip> = claim
+        .excluded
+        .iter()
+        .filter(|(t, decision)| *decision == Decision::Incomplete && t.total() > 0.0)
+        .map(|(t, _)| t)
+        .collect();
+    if !incomplete_trips.is_empty() {
+        println!("\nIncomplete journeys (missed check-out), not claimed.");
+        println!("Request a refund from the operator for these:");
+        let mut table = Table::new();
+        table.add_row(row!["Provider", "Date", "From", "To", "Price"]);
+        for trip in incomplete_trips {
+            table.add_row(row![
+                trip.provider,
+                format_date(trip),
+                trip.from,
+                trip.to,
+                format_price(trip)
+            ]);
+        }
+        table.printstd();
+    }
+}
+
+// Claims recorded in the ledger with their status
+fn print_claims(ledger: &Ledger) {
     let mut table = Table::new();
-    table.add_row(row!["Provider", "Date", "From", "To", "Price"]);
-    for trip in &filtered_ns_trips {
+    table.add_row(row![
+        "Claim",
+        "Recorded",
+        "Period",
+        "Invoice",
+        "Amount",
+        "Status",
+        "Since",
+        "Reference"
+    ]);
+    for record in ledger.claims().unwrap() {
         table.add_row(row![
-            "NS",
-            trip.date,
-            trip.from,
-            trip.to,
-            format!("{:.2}", trip.price)
+            record.id,
+            record.created,
+            record
+                .period
+                .map(|(start, end)| format!("{} - {}", start, end))
+                .unwrap_or_default(),
+            record.invoice,
+            format!("{:.2}", record.total),
+            record.status,
+            record.changed,
+            record.reference.unwrap_or_default()
         ]);
     }
-    for trip in &filtered_gvb_trips {
+    table.printstd();
+}
+
+// Claims that weren't paid yet per status, with the amount still owed
+fn print_outstanding(ledger: &Ledger) {
+    let mut table = Table::new();
+    table.add_row(row!["Status", "Claims", "Amount"]);
+    let mut total = 0.0;
+    for (status, count, amount) in outstanding(&ledger.claims().unwrap()) {
+        table.add_row(row![status, count, format!("{:.2}", amount)]);
+        total += amount;
+    }
+    table.printstd();
+    println!("Outstanding: {:.2}", total);
+}
+
+// Claimed trips with the subtotals per provider
+fn print_claim(claim: &Claim) {
+    // Business invoices get extra columns so the claim can be booked on the right cost center
+    let business = claim.trips.iter().any(|t| t.business.is_some());
+    let mut table = Table::new();
+    if business {
         table.add_row(row![
-            "GVB",
-            trip.date,
-            trip.from,
-            trip.to,
-            format!("{:.2}", trip.price)
+            "Provider",
+            "Date",
+            "From",
+            "To",
+            "Card",
+            "Cost center",
+            "VAT",
+            "Price"
         ]);
+    } else {
+        table.add_row(row!["Provider", "Date", "From", "To", "Price"]);
+    }
+    for trip in &claim.trips {
+        match &trip.business {
+            Some(details) => table.add_row(row![
+                trip.provider,
+                format_date(trip),
+                trip.from,
+                trip.to,
+                details.card_number,
+                details.cost_center,
+                format!("{:.2} ({}%)", details.vat, details.vat_rate),
+                format_price(trip)
+            ]),
+            None if business => table.add_row(row![
+                trip.provider,
+                format_date(trip),
+                trip.from,
+                trip.to,
+                "",
+                "",
+                "",
+                format_price(trip)
+            ]),
+            None => table.add_row(row![
+                trip.provider,
+                format_date(trip),
+                trip.from,
+                trip.to,
+                format_price(trip)
+            ]),
+        };
     }
 
     table.printstd();
-    let ns_total: f32 = filtered_ns_trips.iter().map(|t| t.price).sum();
-    let gvb_total: f32 = filtered_gvb_trips.iter().map(|t| t.price).sum();
-    let grand_total = ns_total + gvb_total;
+
+    if !claim.other.is_empty() {
+        println!("\nOther expenses:");
+        let mut table = Table::new();
+        table.add_row(row!["Category", "Date", "Description", "VAT", "Amount"]);
+        for expense in &claim.other {
+            table.add_row(row![
+                expense.category(),
+                expense.date(),
+                expense.description(),
+                format_vat(expense.as_ref()),
+                format!("{:.2}", expense.amount())
+            ]);
+        }
+        table.printstd();
+    }
+
+    let ns_total = claim.subtotal(Provider::NS);
+    let gvb_total = claim.subtotal(Provider::GVB);
     println!("\nNS subtotal:  {:.2}", ns_total);
     if gvb_total > 0.0 {
         println!("GVB subtotal: {:.2}", gvb_total);
     }
+    for category in claim.other_categories() {
+        println!(
+            "{:<13} {:.2}",
+            format!("{}:", category),
+            claim.category_total(category)
+        );
+    }
     println!("-------------------");
-    println!("Grand total: {:.2}", grand_total);
+    println!("Grand total: {:.2}", claim.total());
+}
+
+// Source of the claim, so the reviewer can find the original invoice
+fn print_invoice_header(invoice: &Invoice) {
+    let unknown = || "-".to_string();
+    println!("Source:   {}", invoice.file.display());
+    println!(
+        "Invoice:  {} of {}",
+        invoice.number.clone().unwrap_or_else(unknown),
+        invoice.date.map(|d| d.to_string()).unwrap_or_else(unknown)
+    );
+    println!(
+        "Period:   {}",
+        invoice
+            .period
+            .map(|(start, end)| format!("{} - {}", start, end))
+            .unwrap_or_else(unknown)
+    );
+    println!(
+        "Customer: {}, card {}\n",
+        invoice.customer_number.clone().unwrap_or_else(unknown),
+        invoice.card_number.clone().unwrap_or_else(unknown)
+    );
 }
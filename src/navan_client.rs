@@ -0,0 +1,160 @@
+use anyhow::{Context, Result};
+
+use crate::expense_client::{ExpenseClient, ExpenseReport, ExpenseRequest, Part};
+
+/// Creates the claim as an expense report in Navan, with the invoices as receipts
+pub struct NavanClient {
+    endpoint: String,
+    token: String,
+}
+
+impl NavanClient {
+    pub fn new(endpoint: &str, token: &str) -> NavanClient {
+        NavanClient {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        }
+    }
+}
+
+impl ExpenseClient for NavanClient {
+    fn request(&self, report: &ExpenseReport) -> Result<ExpenseRequest> {
+        let mut parts = vec![Part {
+            name: "report".to_string(),
+            file_name: None,
+            content_type: "application/json".to_string(),
+            data: serde_json::to_vec_pretty(report)?,
+        }];
+        for attachment in &report.attachments {
+            parts.push(Part {
+                name: "receipt".to_string(),
+                file_name: Some(attachment.file_name.clone()),
+                content_type: attachment.content_type.clone(),
+                data: attachment.data.clone(),
+            });
+        }
+        Ok(ExpenseRequest {
+            url: format!("{}/expense-reports", self.endpoint),
+            headers: vec![
+                (
+                    "Authorization".to_string(),
+                    format!("Bearer {}", self.token),
+                ),
+                ("Accept".to_string(), "application/json".to_string()),
+            ],
+            parts,
+        })
+    }
+
+    fn report_id(&self, response: &str) -> Result<String> {
+        let response: serde_json::Value = serde_json::from_str(response)?;
+        response["id"]
+            .as_str()
+            .map(|id| id.to_string())
+            .context("Navan didn't return a report id")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expense_client::{Attachment, ExpenseLine, submit};
+    use chrono::NaiveDate;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn report() -> ExpenseReport {
+        ExpenseReport {
+            title: "Commuting expenses".to_string(),
+            employee: Some("J. Jansen".to_string()),
+            currency: "EUR".to_string(),
+            total: 5.6,
+            expenses: vec![ExpenseLine {
+                date: NaiveDate::from_ymd_opt(2025, 3, 3).unwrap(),
+                category: "commute".to_string(),
+                description: "NS Hilversum - Amsterdam Centraal".to_string(),
+                amount: 5.6,
+            }],
+            attachments: vec![Attachment {
+                file_name: "invoice.pdf".to_string(),
+                content_type: "application/pdf".to_string(),
+                data: b"%PDF-1.7".to_vec(),
+            }],
+        }
+    }
+
+    // Answers a single request with the given status and body, and hands back what it received
+    fn mock_server(
+        status: &'static str,
+        body: &'static str,
+    ) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut content = vec![0; length];
+            reader.read_exact(&mut content).unwrap();
+            request.push_str(&String::from_utf8_lossy(&content));
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_submit_report() {
+        let (url, server) = mock_server("201 Created", "{\"id\": \"rpt_42\"}");
+        let client = NavanClient::new(&format!("{}/", url), "secret");
+
+        assert_eq!(submit(&client, &report()).unwrap(), "rpt_42");
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /expense-reports HTTP/1.1"));
+        assert!(request.contains("Authorization: Bearer secret"));
+        assert!(request.contains("\"description\": \"NS Hilversum - Amsterdam Centraal\""));
+        assert!(request.contains("filename=\"invoice.pdf\""));
+        assert!(request.contains("%PDF-1.7"));
+    }
+
+    #[test]
+    fn test_submit_rejected() {
+        let (url, server) = mock_server("401 Unauthorized", "{\"error\": \"invalid token\"}");
+        let client = NavanClient::new(&url, "expired");
+
+        let error = submit(&client, &report()).unwrap_err().to_string();
+        server.join().unwrap();
+        assert!(error.contains("401"));
+        assert!(error.contains("invalid token"));
+    }
+
+    #[test]
+    fn test_dry_run_hides_token() {
+        let client = NavanClient::new("https://navan.example", "secret");
+        let printed = client.request(&report()).unwrap().to_string();
+        assert!(printed.starts_with("POST https://navan.example/expense-reports"));
+        assert!(printed.contains("Authorization: <hidden>"));
+        assert!(!printed.contains("secret"));
+        assert!(printed.contains("invoice.pdf, application/pdf (8 bytes)"));
+    }
+}
@@ -1,66 +1,553 @@
+use std::ops::Range;
 use std::{f32, path::PathBuf};
 
 use crate::data::ALL_GVB_STATIONS;
 use crate::data::ALL_NS_STATIONS;
+use crate::data::Adjustment;
+use crate::data::AdjustmentKind;
+use crate::data::Bounds;
+use crate::data::BusinessDetails;
+use crate::data::Invoice;
+use crate::data::InvoiceFormat;
+use crate::data::InvoiceTotals;
+use crate::data::Language;
+use crate::data::Product;
 use crate::data::Provider;
+use crate::data::Source;
 use crate::data::Trip;
 use anyhow::Result;
 
-use chrono::NaiveDate;
-use regex::Regex;
+use chrono::{NaiveDate, NaiveTime};
+use regex::{Captures, Regex};
 
 use pdfium_render::prelude::*;
 
-pub fn ns_pdf_scanner(pdf: PathBuf) -> Result<(Vec<Trip>, Vec<Trip>)> {
+pub fn ns_pdf_scanner(pdf: PathBuf) -> Result<Invoice> {
     let pdfium = Pdfium::default();
 
-    let re_ns = Regex::new(
-        r"^(?P<date>\d{2}-\d{2}-\d{4})\s+NS\s+(?P<kenmerk>.+spits|.+weekend|Treinreizen)\s+(?P<from_to>.+?)\s+(?P<class>\d+)\s+€\s*(?P<price>[\d\.,]+)\s*$",
-    )?;
-    let re_gvb = Regex::new(
-        r"^(?P<date>\d{2}-\d{2}-\d{4})\s+GVB\s+(?P<kenmerk>Lijn(\s\d+)?)\s+(?P<from_to>.+?)\s+€\s*(?P<price>[\d\.,]+)\s*$",
+    let doc = pdfium.load_pdf_from_file(&pdf, None)?;
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut positions: Vec<(usize, Option<Bounds>)> = Vec::new();
+    for (index, page) in doc.pages().iter().enumerate() {
+        if let Ok(text) = page.text() {
+            for (line, bounds) in text_lines(&text) {
+                lines.push(line);
+                positions.push((index, bounds));
+            }
+        }
+    }
+
+    let mut invoice = scan_invoice(pdf, &lines)?;
+    for trip in invoice
+        .ns_trips
+        .iter_mut()
+        .chain(invoice.gvb_trips.iter_mut())
+    {
+        let adjustments = trip.adjustments.iter_mut().map(|a| &mut a.source);
+        for source in std::iter::once(&mut trip.source)
+            .chain(adjustments)
+            .flatten()
+        {
+            locate(source, &positions);
+        }
+    }
+    Ok(invoice)
+}
+
+// A row wrapped over a page break gets a box on both pages
+fn locate(source: &mut Source, positions: &[(usize, Option<Bounds>)]) {
+    source.page = Some(positions[source.lines.start].0);
+    source.bounds = Vec::new();
+    for line in source.lines.clone() {
+        let (page, Some(bounds)) = positions[line] else {
+            continue;
+        };
+        match source.bounds.iter_mut().find(|(other, _)| *other == page) {
+            Some((_, area)) => *area = area.union(bounds),
+            None => source.bounds.push((page, bounds)),
+        }
+    }
+}
+
+// Text of the page split in lines, with the box around the visible characters of each line
+fn text_lines(text: &PdfPageText) -> Vec<(String, Option<Bounds>)> {
+    let mut lines: Vec<(String, Option<Bounds>)> = vec![(String::new(), None)];
+    for char in text.chars().iter() {
+        let Some(c) = char.unicode_char() else {
+            continue;
+        };
+        if c == '\n' {
+            lines.push((String::new(), None));
+            continue;
+        }
+        let (line, bounds) = lines.last_mut().expect("starts with a line");
+        line.push(c);
+        if c.is_whitespace() {
+            continue;
+        }
+        if let Ok(rect) = char.loose_bounds() {
+            let char_bounds = Bounds {
+                left: rect.left().value,
+                bottom: rect.bottom().value,
+                right: rect.right().value,
+                top: rect.top().value,
+            };
+            *bounds = Some(match bounds {
+                Some(line_bounds) => line_bounds.union(char_bounds),
+                None => char_bounds,
+            });
+        }
+    }
+    lines
+}
+
+fn scan_invoice(pdf: PathBuf, lines: &[String]) -> Result<Invoice> {
+    let (ns_trips, gvb_trips) = scan_lines(lines)?;
+    let mut invoice = Invoice::new(pdf, ns_trips, gvb_trips);
+    invoice.format = detect_format(lines);
+    invoice.language = detect_language(lines);
+    invoice.totals = scan_totals(lines)?;
+
+    let date = r"\d{2}-\d{2}-\d{4}";
+    let re_number = Regex::new(r"(?:Factuurnummer|Invoice number):?\s+(?P<value>\S+)")?;
+    let re_date = Regex::new(&format!(
+        r"(?:Factuurdatum|Invoice date):?\s+(?P<value>{date})"
+    ))?;
+    let re_period = Regex::new(&format!(
+        r"(?:Factuurperiode|Periode|Billing period|Period):?\s+(?P<start>{date})\s+(?:t/m|tot en met|to|until|-)\s+(?P<end>{date})"
+    ))?;
+    let re_customer = Regex::new(r"(?:Klantnummer|Customer number):?\s+(?P<value>\S+)")?;
+    let re_card =
+        Regex::new(r"(?:Kaartnummer|Card number):?\s+(?P<value>\d{4}\s?\d{4}\s?\d{4}\s?\d{4})")?;
+    // Only the first occurrence counts, later pages may repeat the header
+    for line in lines {
+        if invoice.number.is_none() {
+            invoice.number = re_number.captures(line).map(|cap| cap["value"].to_string());
+        }
+        if invoice.date.is_none() {
+            invoice.date = re_date
+                .captures(line)
+                .and_then(|cap| NaiveDate::parse_from_str(&cap["value"], "%d-%m-%Y").ok());
+        }
+        if invoice.period.is_none() {
+            invoice.period = re_period.captures(line).and_then(|cap| {
+                Some((
+                    NaiveDate::parse_from_str(&cap["start"], "%d-%m-%Y").ok()?,
+                    NaiveDate::parse_from_str(&cap["end"], "%d-%m-%Y").ok()?,
+                ))
+            });
+        }
+        if invoice.customer_number.is_none() {
+            invoice.customer_number = re_customer
+                .captures(line)
+                .map(|cap| cap["value"].to_string());
+        }
+        if invoice.card_number.is_none() {
+            invoice.card_number = re_card
+                .captures(line)
+                .map(|cap| cap["value"].replace(" ", ""));
+        }
+    }
+    Ok(invoice)
+}
+
+// Business invoices have a cost center column and mention the Businesscard in the header.
+// Personal invoices have neither.
+fn detect_format(lines: &[String]) -> InvoiceFormat {
+    if lines.iter().any(|line| {
+        line.contains("NS-Businesscard")
+            || line.contains("Kostenplaats")
+            || line.contains("Cost center")
+            || line.contains("Cost centre")
+    }) {
+        InvoiceFormat::Business
+    } else {
+        InvoiceFormat::Personal
+    }
+}
+
+// Mijn NS renders the whole invoice in the account language, so counting a few
+// well-known words is enough to tell Dutch and English apart.
+fn detect_language(lines: &[String]) -> Language {
+    const DUTCH: [&str; 5] = ["Treinreizen", "Betaaloverzicht", "Factuur", "Lijn", "Datum"];
+    const ENGLISH: [&str; 5] = [
+        "Train journeys",
+        "Payment overview",
+        "Invoice",
+        "Line",
+        "Date",
+    ];
+    let count = |words: &[&str]| {
+        lines
+            .iter()
+            .filter(|line| words.iter().any(|word| line.contains(word)))
+            .count()
+    };
+    if count(&ENGLISH) > count(&DUTCH) {
+        Language::English
+    } else {
+        Language::Dutch
+    }
+}
+
+fn scan_lines(lines: &[String]) -> Result<(Vec<Trip>, Vec<Trip>)> {
+    let format = detect_format(lines);
+    let language = detect_language(lines);
+
+    let (ns_kenmerk, gvb_kenmerk) = match language {
+        Language::Dutch => (r".+spits|.+weekend|Treinreizen", r"Lijn(\s\d+)?"),
+        Language::English => (r".*[Pp]eak|.*[Ww]eekend|Train journeys", r"Line(\s\d+)?"),
+    };
+    let business_columns = match format {
+        InvoiceFormat::Personal => "",
+        InvoiceFormat::Business => {
+            r"\s+(?P<cost_center>\S+)\s+(?P<vat_rate>\d+(?:[\.,]\d+)?)%\s+€\s*(?P<vat>[\d\.,]+)"
+        }
+    };
+    // Detailed invoices add check-in and check-out times after the date: "08:01 - 08:27"
+    let times = r"(?:\s+(?P<check_in>\d{2}:\d{2})(?:\s*-\s*(?P<check_out>\d{2}:\d{2}))?)?";
+    let re_ns = Regex::new(&format!(
+        r"^(?P<date>\d{{2}}-\d{{2}}-\d{{4}}){times}\s+NS\s+(?P<kenmerk>{ns_kenmerk})\s+(?P<from_to>.+?)\s+(?P<class>\d+){business_columns}\s+€\s*(?P<price>[\d\.,]+)\s*$"
+    ))?;
+    let re_gvb = Regex::new(&format!(
+        r"^(?P<date>\d{{2}}-\d{{2}}-\d{{4}}){times}\s+GVB\s+(?P<kenmerk>{gvb_kenmerk})\s+(?P<from_to>.+?){business_columns}\s+€\s*(?P<price>[\d\.,]+)\s*$"
+    ))?;
+    // Business invoices group trips per card: "Kaart 3528 0123 4567 8901 - J. Jansen"
+    let re_card = Regex::new(
+        r"^(?:Kaart|Card)(?:nummer| number)?:?\s+(?P<card>\d{4}\s?\d{4}\s?\d{4}\s?\d{4})(?:\s+[-–]\s+(?P<holder>.+?))?\s*$",
     )?;
+    let re_holder = Regex::new(r"^(?:Kaarthouder|Card holder|Cardholder):?\s+(?P<holder>.+?)\s*$")?;
+    // Supplements, corrections, refunds and credits. The route is optional, credits
+    // usually don't have one. Amounts may carry a minus sign on either side of the €.
+    let adjustment_kinds = match language {
+        Language::Dutch => r"Toeslag|Correctie|Restitutie|Terugbetaling|Tegoed|Creditering",
+        Language::English => r"Supplement|Surcharge|Correction|Refund|Credit",
+    };
+    let re_adjustment = Regex::new(&format!(
+        r"^(?P<date>\d{{2}}-\d{{2}}-\d{{4}})\s+(?P<provider>NS|GVB)\s+(?P<kind>{adjustment_kinds})(?P<rest>.*?)(?:\s+\d+)?\s+(?P<price>-?\s*€\s*-?\s*[\d\.,]+-?)\s*$"
+    ))?;
 
-    let doc = pdfium.load_pdf_from_file(&pdf, None)?;
+    let rows = reassemble_rows(lines, |row| {
+        if let Some(cap) = re_adjustment.captures(row) {
+            let provider = parse_provider(&cap["provider"]);
+            let (description, from_to) = split_description(cap["rest"].trim(), &provider);
+            let (from, to) = extract_stations(from_to, provider);
+            return from_to.is_empty() && description.is_empty()
+                || !from.is_empty() && !to.is_empty();
+        }
+        [(&re_ns, Provider::NS), (&re_gvb, Provider::GVB)]
+            .into_iter()
+            .any(|(re, provider)| {
+                re.captures(row).is_some_and(|cap| {
+                    let (from, to) = extract_stations(&cap["from_to"], provider);
+                    !from.is_empty() && !to.is_empty()
+                })
+            })
+    })?;
 
     let mut gvb_trips: Vec<Trip> = Vec::new();
     let mut ns_trips: Vec<Trip> = Vec::new();
+    let mut adjustments: Vec<(NaiveDate, Provider, String, String, Adjustment)> = Vec::new();
+    let mut card_number = String::new();
+    let mut card_holder: Option<String> = None;
 
-    for (_, page) in doc.pages().iter().enumerate() {
-        if let Ok(text) = page.text() {
-            for line in text.all().split("\n") {
-                if let Some(cap_ns) = re_ns.captures(&line) {
-                    let (from, to) = extract_stations(&cap_ns["from_to"], Provider::NS);
-                    ns_trips.push(Trip::new(
-                        NaiveDate::parse_from_str(&cap_ns["date"], "%d-%m-%Y")?,
-                        Provider::NS,
-                        from,
-                        to,
-                        parse_price(&cap_ns["price"]).unwrap(),
-                    ));
+    for (span, line) in &rows {
+        if let Some(cap_adj) = re_adjustment.captures(line) {
+            let provider = parse_provider(&cap_adj["provider"]);
+            let (description, from_to) = split_description(cap_adj["rest"].trim(), &provider);
+            let (from, to) = extract_stations(from_to, provider.clone());
+            let kind = parse_adjustment_kind(&cap_adj["kind"]);
+            let amount = parse_price(&cap_adj["price"], language).unwrap_or_default();
+            // Refunds and credits are money coming back, whether or not the invoice prints a minus
+            let amount = match kind {
+                AdjustmentKind::Refund | AdjustmentKind::Credit => -amount.abs(),
+                AdjustmentKind::Supplement => amount.abs(),
+                AdjustmentKind::Correction => amount,
+            };
+            adjustments.push((
+                NaiveDate::parse_from_str(&cap_adj["date"], "%d-%m-%Y")?,
+                provider,
+                from,
+                to,
+                Adjustment {
+                    kind,
+                    description: format!("{} {}", &cap_adj["kind"], description)
+                        .trim()
+                        .to_string(),
+                    amount,
+                    source: Some(Source::new(span.clone())),
+                },
+            ));
+            continue;
+        }
+
+        if format == InvoiceFormat::Business {
+            if let Some(cap_card) = re_card.captures(line) {
+                card_number = cap_card["card"].replace(" ", "");
+                card_holder = cap_card.name("holder").map(|m| m.as_str().to_string());
+                continue;
+            }
+            if let Some(cap_holder) = re_holder.captures(line) {
+                card_holder = Some(cap_holder["holder"].to_string());
+                continue;
+            }
+        }
+        if let Some(cap_ns) = re_ns.captures(line) {
+            let (from, to) = extract_stations(&cap_ns["from_to"], Provider::NS);
+            let mut trip = Trip::new(
+                NaiveDate::parse_from_str(&cap_ns["date"], "%d-%m-%Y")?,
+                Provider::NS,
+                from,
+                to,
+                parse_price(&cap_ns["price"], language).unwrap(),
+            )
+            .with_product(parse_product(&cap_ns["kenmerk"], language))
+            .with_source(Source::new(span.clone()));
+            if let Some((check_in, check_out)) = parse_times(&cap_ns) {
+                trip = trip.with_times(check_in, check_out);
+            }
+            if format == InvoiceFormat::Business {
+                trip = trip.with_business(business_details(
+                    &cap_ns,
+                    &card_number,
+                    &card_holder,
+                    language,
+                ));
+            }
+            ns_trips.push(trip);
+        }
+        if let Some(cap_gvb) = re_gvb.captures(line) {
+            let (from, to) = extract_stations(&cap_gvb["from_to"], Provider::GVB);
+            // A missing station is a missed check-out, which still needs to be reported
+            if !to.is_empty() || !from.is_empty() {
+                let mut trip = Trip::new(
+                    NaiveDate::parse_from_str(&cap_gvb["date"], "%d-%m-%Y")?,
+                    Provider::GVB,
+                    from,
+                    to,
+                    parse_price(&cap_gvb["price"], language).unwrap(),
+                )
+                .with_product(parse_product(&cap_gvb["kenmerk"], language))
+                .with_source(Source::new(span.clone()));
+                if let Some((check_in, check_out)) = parse_times(&cap_gvb) {
+                    trip = trip.with_times(check_in, check_out);
                 }
-                if let Some(cap_gvb) = re_gvb.captures(&line) {
-                    let (from, to) = extract_stations(&cap_gvb["from_to"], Provider::GVB);
-                    if !to.is_empty() && !from.is_empty() {
-                        gvb_trips.push(Trip::new(
-                            NaiveDate::parse_from_str(&cap_gvb["date"], "%d-%m-%Y")?,
-                            Provider::GVB,
-                            from,
-                            to,
-                            parse_price(&cap_gvb["price"]).unwrap(),
-                        ));
-                    }
+                if format == InvoiceFormat::Business {
+                    trip = trip.with_business(business_details(
+                        &cap_gvb,
+                        &card_number,
+                        &card_holder,
+                        language,
+                    ));
                 }
+                gvb_trips.push(trip);
             }
         }
     }
 
+    for (date, provider, from, to, adjustment) in adjustments {
+        let trips = match provider {
+            Provider::NS => &mut ns_trips,
+            Provider::GVB => &mut gvb_trips,
+        };
+        link_adjustment(trips, date, provider, from, to, adjustment);
+    }
+
     Ok((ns_trips, gvb_trips))
 }
 
-fn parse_price(s: &str) -> Option<f32> {
-    let normalized = s.replace(",", ".");
-    normalized.parse::<f32>().ok()
+// An adjustment belongs to the trip with the same date and route. When the same trip was
+// made twice that day, the first one that doesn't have this kind of adjustment yet gets it.
+// Adjustments without a matching trip (credits, corrections of older invoices) are kept as
+// a zero priced trip so they still count towards what was paid.
+fn link_adjustment(
+    trips: &mut Vec<Trip>,
+    date: NaiveDate,
+    provider: Provider,
+    from: String,
+    to: String,
+    adjustment: Adjustment,
+) {
+    let mut candidates: Vec<&mut Trip> = trips
+        .iter_mut()
+        .filter(|t| t.price != 0.0 && t.date == date && t.from == from && t.to == to)
+        .collect();
+    if candidates.is_empty() {
+        // The adjustment's row is the only row of the trip
+        let mut trip = Trip::new(date, provider, from, to, 0.0);
+        trip.source = adjustment.source.clone();
+        trips.push(trip.with_adjustment(adjustment));
+        return;
+    }
+    let free = candidates
+        .iter()
+        .position(|t| !t.adjustments.iter().any(|a| a.kind == adjustment.kind))
+        .unwrap_or(0);
+    candidates.swap_remove(free).adjustments.push(adjustment);
+}
+
+fn parse_provider(s: &str) -> Provider {
+    match s {
+        "GVB" => Provider::GVB,
+        _ => Provider::NS,
+    }
+}
+
+fn parse_adjustment_kind(kind: &str) -> AdjustmentKind {
+    match kind {
+        "Toeslag" | "Supplement" | "Surcharge" => AdjustmentKind::Supplement,
+        "Restitutie" | "Terugbetaling" | "Refund" => AdjustmentKind::Refund,
+        "Tegoed" | "Creditering" | "Credit" => AdjustmentKind::Credit,
+        _ => AdjustmentKind::Correction,
+    }
+}
+
+// "Intercity direct Rotterdam Centraal Schiphol" -> ("Intercity direct", "Rotterdam Centraal Schiphol")
+fn split_description<'a>(s: &'a str, provider: &Provider) -> (&'a str, &'a str) {
+    let station_list: &'static [&'static str] = match provider {
+        Provider::NS => &ALL_NS_STATIONS[..],
+        Provider::GVB => &ALL_GVB_STATIONS[..],
+    };
+    let route_start = s
+        .char_indices()
+        .filter(|(i, _)| *i == 0 || s[..*i].ends_with(' '))
+        .map(|(i, _)| i)
+        .find(|i| {
+            station_list
+                .iter()
+                .any(|station| s[*i..].starts_with(station))
+        });
+    match route_start {
+        Some(i) => (s[..i].trim(), &s[i..]),
+        None => (s, ""),
+    }
+}
+
+// Total lines printed by the invoice itself, "Totaal NS € 123,45" or "Total amount € 135.75".
+// Read from the raw lines, they are never part of a trip row. The last one wins when every
+// page repeats them.
+fn scan_totals(lines: &[String]) -> Result<InvoiceTotals> {
+    let language = detect_language(lines);
+    let re_total = Regex::new(
+        r"^(?:(?:Totaal|Total)(?:bedrag| amount)?(?:\s+(?P<provider>NS|GVB))?|Te betalen|Amount due):?\s+(?P<price>-?\s*€\s*-?\s*[\d\.,]+-?)\s*$",
+    )?;
+    let mut totals = InvoiceTotals::default();
+    for line in lines {
+        if let Some(cap) = re_total.captures(line.trim()) {
+            let amount = parse_price(&cap["price"], language);
+            match cap.name("provider").map(|m| m.as_str()) {
+                Some("NS") => totals.ns = amount,
+                Some(_) => totals.gvb = amount,
+                None => totals.total = amount,
+            }
+        }
+    }
+    Ok(totals)
+}
+
+// Long station pairs wrap inside their table cell and pdfium returns the overflow as a
+// separate line, either before or after the remaining columns of the row. A row that
+// starts with a date but doesn't yield both stations absorbs the following lines until
+// it does.
+fn reassemble_rows(
+    lines: &[String],
+    is_complete: impl Fn(&str) -> bool,
+) -> Result<Vec<(Range<usize>, String)>> {
+    let re_row_start = Regex::new(r"^\d{2}-\d{2}-\d{4}\s")?;
+    let mut rows: Vec<(Range<usize>, String)> = Vec::new();
+    let mut open_row = false;
+    for (index, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if open_row && !line.is_empty() && !re_row_start.is_match(line) {
+            let (span, row) = rows.last_mut().expect("open_row is only set after a push");
+            if let Some(joined) = join_continuation(row, line, &is_complete) {
+                open_row = !is_complete(&joined);
+                *row = joined;
+                span.end = index + 1;
+                continue;
+            }
+        }
+        open_row = re_row_start.is_match(line) && !is_complete(line);
+        rows.push((index..index + 1, line.to_string()));
+    }
+    Ok(rows)
+}
+
+fn join_continuation(
+    row: &str,
+    continuation: &str,
+    is_complete: &impl Fn(&str) -> bool,
+) -> Option<String> {
+    let appended = format!("{} {}", row, continuation);
+    // No price yet, so the rest of the row is still to come
+    if !row.contains('€') || is_complete(&appended) {
+        return Some(appended);
+    }
+    // The overflow came after the price, put it back in front of the trailing columns
+    row.char_indices()
+        .rev()
+        .filter(|(_, c)| *c == ' ')
+        .map(|(i, _)| format!("{} {}{}", &row[..i], continuation, &row[i..]))
+        .find(|candidate| is_complete(candidate))
+}
+
+fn parse_times(cap: &Captures) -> Option<(NaiveTime, Option<NaiveTime>)> {
+    let check_in = NaiveTime::parse_from_str(cap.name("check_in")?.as_str(), "%H:%M").ok()?;
+    let check_out = cap
+        .name("check_out")
+        .and_then(|m| NaiveTime::parse_from_str(m.as_str(), "%H:%M").ok());
+    Some((check_in, check_out))
+}
+
+fn business_details(
+    cap: &Captures,
+    card_number: &str,
+    card_holder: &Option<String>,
+    language: Language,
+) -> BusinessDetails {
+    BusinessDetails {
+        card_number: card_number.to_string(),
+        card_holder: card_holder.clone(),
+        cost_center: cap["cost_center"].to_string(),
+        vat_rate: parse_price(&cap["vat_rate"], language).unwrap_or_default(),
+        vat: parse_price(&cap["vat"], language).unwrap_or_default(),
+    }
+}
+
+// Dutch invoices write 1.234,56 and English ones 1,234.56. Negative amounts show up as
+// "-€ 1,20", "€ -1,20" or "€ 1,20-".
+fn parse_price(s: &str, language: Language) -> Option<f32> {
+    let negative = s.contains('-');
+    let digits: String = s
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .collect();
+    let normalized = match language {
+        Language::Dutch => digits.replace(".", "").replace(",", "."),
+        Language::English => digits.replace(",", ""),
+    };
+    let price = normalized.parse::<f32>().ok()?;
+    Some(if negative { -price } else { price })
+}
+
+fn parse_product(kenmerk: &str, language: Language) -> Product {
+    let kenmerk = kenmerk.to_lowercase();
+    let (off_peak, peak, weekend, line) = match language {
+        Language::Dutch => ("buiten de spits", "spits", "weekend", "lijn"),
+        Language::English => ("off-peak", "peak", "weekend", "line"),
+    };
+    if let Some(number) = kenmerk.strip_prefix(line) {
+        Product::Line(number.trim().parse().ok())
+    } else if kenmerk.contains(off_peak) || kenmerk.starts_with("dal") {
+        Product::OffPeak
+    } else if kenmerk.ends_with(peak) {
+        Product::Peak
+    } else if kenmerk.ends_with(weekend) {
+        Product::Weekend
+    } else {
+        Product::TrainJourneys
+    }
 }
 
 fn extract_stations(s: &str, provider: Provider) -> (String, String) {
@@ -77,5 +564,284 @@ fn extract_stations(s: &str, provider: Provider) -> (String, String) {
             end = station.to_string();
         }
     }
+    // Only the check-in station: the check-out was missed
+    if start == s.trim() {
+        end.clear();
+    }
     (start, end)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_scan_personal_invoice() {
+        let invoice = lines(
+            "Betaaloverzicht\n\
+             03-03-2025 NS Treinreizen Hilversum Amsterdam Centraal 2 € 5,60\n\
+             03-03-2025 GVB Lijn 5 Centraal Station Rokin € 1,20",
+        );
+        assert_eq!(detect_format(&invoice), InvoiceFormat::Personal);
+
+        let (ns, gvb) = scan_lines(&invoice).unwrap();
+        assert_eq!(ns.len(), 1);
+        assert_eq!(ns[0].from, "Hilversum");
+        assert_eq!(ns[0].to, "Amsterdam Centraal");
+        assert_eq!(ns[0].price, 5.6);
+        assert!(ns[0].business.is_none());
+        assert_eq!(gvb.len(), 1);
+        assert_eq!(gvb[0].to, "Rokin");
+    }
+
+    #[test]
+    fn test_scan_business_invoice() {
+        let invoice = lines(
+            "NS-Businesscard overzicht\n\
+             Datum Vervoerder Kenmerk Van Naar Klasse Kostenplaats Btw Prijs\n\
+             Kaart 3528 0123 4567 8901 - J. Jansen\n\
+             03-03-2025 NS Treinreizen Hilversum Amsterdam Centraal 2 KP-1001 9% € 0,46 € 5,60\n\
+             03-03-2025 GVB Lijn 5 Centraal Station Rokin KP-1001 9% € 0,10 € 1,20",
+        );
+        assert_eq!(detect_format(&invoice), InvoiceFormat::Business);
+
+        let (ns, gvb) = scan_lines(&invoice).unwrap();
+        assert_eq!(ns.len(), 1);
+        assert_eq!(ns[0].price, 5.6);
+        assert_eq!(
+            ns[0].business,
+            Some(BusinessDetails {
+                card_number: "3528012345678901".into(),
+                card_holder: Some("J. Jansen".into()),
+                cost_center: "KP-1001".into(),
+                vat_rate: 9.0,
+                vat: 0.46,
+            })
+        );
+        assert_eq!(gvb.len(), 1);
+        assert_eq!(gvb[0].business.as_ref().unwrap().vat, 0.1);
+    }
+
+    #[test]
+    fn test_scan_english_invoice() {
+        let invoice = lines(
+            "Payment overview\n\
+             Date Carrier Product From To Class Price\n\
+             03-03-2025 NS Off-peak Hilversum Amsterdam Centraal 2 € 5.60\n\
+             04-03-2025 NS Train journeys Amsterdam Zuid Hilversum 2 € 1,005.60\n\
+             04-03-2025 GVB Line 5 Centraal Station Rokin € 1.20",
+        );
+        assert_eq!(detect_language(&invoice), Language::English);
+
+        let (ns, gvb) = scan_lines(&invoice).unwrap();
+        assert_eq!(ns.len(), 2);
+        assert_eq!(ns[0].product, Some(Product::OffPeak));
+        assert_eq!(ns[0].price, 5.6);
+        assert_eq!(ns[1].product, Some(Product::TrainJourneys));
+        assert_eq!(ns[1].price, 1005.6);
+        assert_eq!(gvb.len(), 1);
+        assert_eq!(gvb[0].product, Some(Product::Line(Some(5))));
+        assert_eq!(gvb[0].price, 1.2);
+    }
+
+    #[test]
+    fn test_parse_product_same_in_both_languages() {
+        assert_eq!(
+            parse_product("Dal Voordeel buiten de spits", Language::Dutch),
+            parse_product("Off-peak", Language::English)
+        );
+        assert_eq!(
+            parse_product("Treinreizen", Language::Dutch),
+            parse_product("Train journeys", Language::English)
+        );
+        assert_eq!(
+            parse_product("Lijn 24", Language::Dutch),
+            parse_product("Line 24", Language::English)
+        );
+    }
+
+    #[test]
+    fn test_scan_wrapped_rows() {
+        let invoice = lines(
+            "Betaaloverzicht\n\
+             03-03-2025 NS Treinreizen Den Haag Hollands Spoor Amsterdam\n\
+             Bijlmer ArenA 2 € 12,30\n\
+             04-03-2025 NS Treinreizen Amsterdam Bijlmer ArenA Den Haag 2 € 12,30\n\
+             Hollands Spoor\n\
+             05-03-2025 NS Treinreizen Hilversum Amsterdam Centraal 2 € 5,60\n\
+             Pagina 1 van 2",
+        );
+
+        let (ns, _) = scan_lines(&invoice).unwrap();
+        assert_eq!(ns.len(), 3);
+        assert_eq!(ns[0].from, "Den Haag Hollands Spoor");
+        assert_eq!(ns[0].to, "Amsterdam Bijlmer ArenA");
+        assert_eq!(ns[1].from, "Amsterdam Bijlmer ArenA");
+        assert_eq!(ns[1].to, "Den Haag Hollands Spoor");
+        assert_eq!(ns[1].price, 12.3);
+        assert_eq!(ns[2].to, "Amsterdam Centraal");
+        // Wrapped rows point at every line they were assembled from
+        let spans: Vec<_> = ns.iter().map(|t| t.source.clone().unwrap().lines).collect();
+        assert_eq!(spans, vec![1..3, 3..5, 5..6]);
+    }
+
+    #[test]
+    fn test_locate_row_over_page_break() {
+        let line = |bottom: f32, right: f32| Bounds {
+            left: 40.0,
+            bottom,
+            right,
+            top: bottom + 10.0,
+        };
+        let positions = vec![
+            (0, Some(line(100.0, 550.0))),
+            (0, Some(line(60.0, 500.0))),
+            (0, None),
+            (1, Some(line(780.0, 200.0))),
+        ];
+        let mut source = Source::new(1..4);
+        locate(&mut source, &positions);
+        assert_eq!(source.page, Some(0));
+        assert_eq!(
+            source.bounds,
+            vec![(0, line(60.0, 500.0)), (1, line(780.0, 200.0))]
+        );
+    }
+
+    #[test]
+    fn test_parse_negative_price() {
+        assert_eq!(parse_price("-€ 1,20", Language::Dutch), Some(-1.2));
+        assert_eq!(parse_price("€ -1,20", Language::Dutch), Some(-1.2));
+        assert_eq!(parse_price("€ 1.234,50-", Language::Dutch), Some(-1234.5));
+        assert_eq!(parse_price("€ -1,234.50", Language::English), Some(-1234.5));
+    }
+
+    #[test]
+    fn test_scan_adjustments() {
+        let invoice = lines(
+            "Betaaloverzicht\n\
+             03-03-2025 NS Treinreizen Rotterdam Centraal Schiphol 2 € 12,00\n\
+             03-03-2025 NS Toeslag Intercity direct Rotterdam Centraal Schiphol € 2,60\n\
+             04-03-2025 NS Treinreizen Hilversum Amsterdam Centraal 2 € 20,00\n\
+             04-03-2025 NS Restitutie vergeten uitcheck Hilversum Amsterdam Centraal € 20,00\n\
+             05-03-2025 NS Tegoed -€ 5,00",
+        );
+
+        let (ns, _) = scan_lines(&invoice).unwrap();
+        assert_eq!(ns.len(), 3);
+        assert_eq!(ns[0].adjustments[0].kind, AdjustmentKind::Supplement);
+        assert_eq!(ns[0].adjustments[0].description, "Toeslag Intercity direct");
+        assert_eq!(ns[0].total(), 14.6);
+        assert_eq!(ns[1].adjustments[0].kind, AdjustmentKind::Refund);
+        assert_eq!(ns[1].total(), 0.0);
+        // Credit without a route is kept on its own
+        assert_eq!(ns[2].price, 0.0);
+        assert_eq!(ns[2].total(), -5.0);
+        assert_eq!(ns[2].source.clone().unwrap().lines, 5..6);
+    }
+
+    #[test]
+    fn test_scan_keeps_missed_check_out() {
+        let invoice = lines(
+            "Betaaloverzicht\n\
+             03-03-2025 NS Treinreizen Hilversum 2 € 20,00\n\
+             03-03-2025 GVB Lijn 5 Centraal Station € 4,00",
+        );
+
+        let (ns, gvb) = scan_lines(&invoice).unwrap();
+        assert_eq!(ns.len(), 1);
+        assert!(ns[0].is_incomplete());
+        assert_eq!(gvb.len(), 1);
+        assert_eq!(gvb[0].from, "Centraal Station");
+        assert!(gvb[0].is_incomplete());
+    }
+
+    #[test]
+    fn test_scan_complete_trip_at_boarding_fare() {
+        let invoice = lines(
+            "Betaaloverzicht\n\
+             03-03-2025 NS Treinreizen Maastricht Den Helder 2 € 20,00",
+        );
+
+        let (ns, _) = scan_lines(&invoice).unwrap();
+        assert_eq!(ns[0].to, "Den Helder");
+        assert!(!ns[0].is_incomplete());
+        assert!(ns[0].is_suspect());
+    }
+
+    #[test]
+    fn test_scan_check_in_check_out_times() {
+        let invoice = lines(
+            "Betaaloverzicht\n\
+             03-03-2025 08:01 - 08:27 NS Treinreizen Hilversum Amsterdam Centraal 2 € 5,60\n\
+             03-03-2025 23:50 - 00:14 NS Treinreizen Amsterdam Centraal Hilversum 2 € 5,60\n\
+             03-03-2025 08:35 GVB Lijn 5 Centraal Station Rokin € 1,20",
+        );
+
+        let (ns, gvb) = scan_lines(&invoice).unwrap();
+        let at = |d: u32, h: u32, m: u32| {
+            NaiveDate::from_ymd_opt(2025, 3, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        assert_eq!(ns[0].check_in, Some(at(3, 8, 1)));
+        assert_eq!(ns[0].check_out, Some(at(3, 8, 27)));
+        assert_eq!(ns[1].check_out, Some(at(4, 0, 14)));
+        assert_eq!(gvb[0].check_in, Some(at(3, 8, 35)));
+        assert_eq!(gvb[0].check_out, None);
+    }
+
+    #[test]
+    fn test_scan_totals() {
+        let invoice = lines(
+            "Betaaloverzicht\n\
+             03-03-2025 NS Treinreizen Hilversum Amsterdam Centraal 2 € 5,60\n\
+             Totaal NS € 5,60\n\
+             Totaal GVB € 0,00\n\
+             Te betalen € 5,60",
+        );
+
+        let totals = scan_totals(&invoice).unwrap();
+        assert_eq!(totals.ns, Some(5.6));
+        assert_eq!(totals.gvb, Some(0.0));
+        assert_eq!(totals.total, Some(5.6));
+
+        let (ns, gvb) = scan_lines(&invoice).unwrap();
+        assert!(totals.differences(&ns, &gvb).is_empty());
+        assert_eq!(
+            totals.differences(&[], &gvb),
+            vec![("NS", 5.6, 0.0), ("Total", 5.6, 0.0)]
+        );
+    }
+
+    #[test]
+    fn test_scan_invoice_metadata() {
+        let invoice = lines(
+            "Betaaloverzicht\n\
+             Factuurnummer: 2025031234 Factuurdatum: 02-04-2025\n\
+             Klantnummer: 987654321\n\
+             Kaartnummer: 3528 0123 4567 8901\n\
+             Periode 01-03-2025 t/m 31-03-2025\n\
+             03-03-2025 NS Treinreizen Hilversum Amsterdam Centraal 2 € 5,60",
+        );
+
+        let invoice = scan_invoice(PathBuf::from("invoice.pdf"), &invoice).unwrap();
+        assert_eq!(invoice.number.as_deref(), Some("2025031234"));
+        assert_eq!(invoice.date, NaiveDate::from_ymd_opt(2025, 4, 2));
+        assert_eq!(
+            invoice.period,
+            Some((
+                NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2025, 3, 31).unwrap()
+            ))
+        );
+        assert_eq!(invoice.customer_number.as_deref(), Some("987654321"));
+        assert_eq!(invoice.card_number.as_deref(), Some("3528012345678901"));
+        assert_eq!(invoice.ns_trips.len(), 1);
+    }
+}
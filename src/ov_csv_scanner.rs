@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use crate::data::ALL_GVB_STATIONS;
+use crate::data::ALL_NS_STATIONS;
+use crate::data::Invoice;
+use crate::data::Provider;
+use crate::data::Source;
+use crate::data::Trip;
+use anyhow::Result;
+
+use chrono::{NaiveDate, NaiveTime};
+
+// Transaction overview exported from https://www.ov-chipkaart.nl, one journey per row:
+// "Datum";"Check-in";"Vertrek";"Check-uit";"Bestemming";"Bedrag";"Transactie";...
+pub fn ov_csv_scanner(csv: PathBuf) -> Result<Invoice> {
+    let reader = csv::ReaderBuilder::new()
+        .delimiter(b';')
+        .flexible(true)
+        .from_path(&csv)?;
+    scan_records(csv, reader)
+}
+
+fn scan_records<R: std::io::Read>(csv: PathBuf, mut reader: csv::Reader<R>) -> Result<Invoice> {
+    let mut gvb_trips: Vec<Trip> = Vec::new();
+    let mut ns_trips: Vec<Trip> = Vec::new();
+    let mut card_number: Option<String> = None;
+    let mut skipped: Vec<String> = Vec::new();
+
+    for record in reader.records() {
+        let record = record?;
+        let field = |index: usize| record.get(index).unwrap_or_default().trim();
+        let (date, check_in, from, check_out, to, amount) =
+            (field(0), field(1), field(2), field(3), field(4), field(5));
+        if card_number.is_none() && !field(11).is_empty() {
+            card_number = Some(field(11).replace(" ", ""));
+        }
+        // Top-ups and product purchases have no departure
+        if from.is_empty() {
+            continue;
+        }
+        // The export doesn't name the operator, but NS and GVB stop names don't overlap. Rides
+        // with RET, HTM, Arriva and the like match neither and are left out.
+        let known = |stations: &[&str]| stations.contains(&from) || stations.contains(&to);
+        let provider = if known(&ALL_NS_STATIONS) {
+            Provider::NS
+        } else if known(&ALL_GVB_STATIONS) {
+            Provider::GVB
+        } else {
+            skipped.push(format!("{} {} - {}", date, from, to));
+            continue;
+        };
+        // 0-based like the PDF lines, the header is line 0
+        let line = record
+            .position()
+            .map_or(0, |position| position.line() as usize - 1);
+        let mut trip = Trip::new(
+            NaiveDate::parse_from_str(date, "%d-%m-%Y")?,
+            provider.clone(),
+            from.to_string(),
+            to.to_string(),
+            amount
+                .replace(".", "")
+                .replace(",", ".")
+                .parse::<f32>()
+                .unwrap_or_default(),
+        )
+        .with_source(Source::new(line..line + 1));
+        if let Ok(check_in) = NaiveTime::parse_from_str(check_in, "%H:%M") {
+            trip = trip.with_times(check_in, NaiveTime::parse_from_str(check_out, "%H:%M").ok());
+        }
+        match provider {
+            Provider::NS => ns_trips.push(trip),
+            Provider::GVB => gvb_trips.push(trip),
+        }
+    }
+
+    // An export isn't an invoice, it only knows the card it was made for
+    let mut invoice = Invoice::new(csv, ns_trips, gvb_trips);
+    invoice.card_number = card_number;
+    invoice.skipped = skipped;
+    Ok(invoice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_ov_chipkaart_export() {
+        let export = "\"Datum\";\"Check-in\";\"Vertrek\";\"Check-uit\";\"Bestemming\";\"Bedrag\";\"Transactie\"\n\
+             \"03-03-2025\";\"08:01\";\"Hilversum\";\"08:27\";\"Amsterdam Centraal\";\"5,60\";\"Check-uit\"\n\
+             \"03-03-2025\";\"08:35\";\"Centraal Station\";\"08:44\";\"Rokin\";\"1,20\";\"Check-uit\"\n\
+             \"03-03-2025\";\"18:10\";\"Rotterdam, Beurs\";\"18:25\";\"Rotterdam, Kralingse Zoom\";\"1,90\";\"Check-uit\"\n\
+             \"03-03-2025\";\"\";\"\";\"\";\"\";\"20,00\";\"Saldo opgeladen\"";
+        let reader = csv::ReaderBuilder::new()
+            .delimiter(b';')
+            .from_reader(export.as_bytes());
+
+        let invoice = scan_records(PathBuf::from("export.csv"), reader).unwrap();
+        let (ns, gvb) = (invoice.ns_trips, invoice.gvb_trips);
+        assert_eq!(ns.len(), 1);
+        assert_eq!(ns[0].price, 5.6);
+        assert_eq!(
+            ns[0].check_out.unwrap().time(),
+            NaiveTime::from_hms_opt(8, 27, 0).unwrap()
+        );
+        assert_eq!(gvb.len(), 1);
+        assert_eq!(gvb[0].to, "Rokin");
+        assert_eq!(
+            invoice.skipped,
+            vec!["03-03-2025 Rotterdam, Beurs - Rotterdam, Kralingse Zoom"]
+        );
+        let source = gvb[0].source.as_ref().unwrap();
+        assert_eq!(source.file, PathBuf::from("export.csv"));
+        assert_eq!(source.lines, 2..3);
+    }
+}
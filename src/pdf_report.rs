@@ -0,0 +1,255 @@
+use std::path::Path;
+
+use anyhow::Result;
+use pdfium_render::prelude::*;
+
+use crate::claim::{Claim, format_date};
+use crate::data::Provider;
+
+// A4 in points, with 2 cm margins
+const MARGIN: f32 = 56.0;
+const TOP: f32 = 842.0 - MARGIN;
+const RIGHT: f32 = 595.0 - MARGIN;
+const LINE_HEIGHT: f32 = 14.0;
+const FONT_SIZE: f32 = 10.0;
+
+// Left edge of the trip table columns: Provider, Date, From, To, Price
+const COLUMNS: [f32; 5] = [MARGIN, 100.0, 205.0, 350.0, 490.0];
+
+enum Line {
+    Title(String),
+    Text(Vec<(f32, String)>, bool), // Cells with their left edge, bold or not
+    Rule,
+    Gap,
+}
+
+/// Writes the claim as a document that can be signed and handed in
+pub fn pdf_report(claim: &Claim, output: &Path) -> Result<()> {
+    let pdfium = Pdfium::default();
+    let mut document = pdfium.create_new_pdf()?;
+    let regular = document.fonts_mut().helvetica();
+    let bold = document.fonts_mut().helvetica_bold();
+
+    let mut page = document
+        .pages_mut()
+        .create_page_at_end(PdfPagePaperSize::a4())?;
+    let mut y = TOP;
+    for line in layout(claim) {
+        if y < MARGIN {
+            page = document
+                .pages_mut()
+                .create_page_at_end(PdfPagePaperSize::a4())?;
+            y = TOP;
+        }
+        let objects = page.objects_mut();
+        match line {
+            Line::Title(text) => {
+                objects.create_text_object(
+                    PdfPoints::new(MARGIN),
+                    PdfPoints::new(y),
+                    text,
+                    bold,
+                    PdfPoints::new(16.0),
+                )?;
+                y -= 2.0 * LINE_HEIGHT;
+            }
+            Line::Text(cells, is_bold) => {
+                let font = if is_bold { bold } else { regular };
+                for (x, text) in cells {
+                    objects.create_text_object(
+                        PdfPoints::new(x),
+                        PdfPoints::new(y),
+                        text,
+                        font,
+                        PdfPoints::new(FONT_SIZE),
+                    )?;
+                }
+                y -= LINE_HEIGHT;
+            }
+            Line::Rule => {
+                objects.create_path_object_line(
+                    PdfPoints::new(MARGIN),
+                    PdfPoints::new(y + LINE_HEIGHT / 2.0),
+                    PdfPoints::new(RIGHT),
+                    PdfPoints::new(y + LINE_HEIGHT / 2.0),
+                    PdfColor::new(0, 0, 0, 255),
+                    PdfPoints::new(0.5),
+                )?;
+                y -= LINE_HEIGHT / 2.0;
+            }
+            Line::Gap => y -= LINE_HEIGHT,
+        }
+    }
+
+    document.save_to_file(output)?;
+    Ok(())
+}
+
+// Everything on the document top to bottom, page breaks are left to the writer
+fn layout(claim: &Claim) -> Vec<Line> {
+    let label = |label: &str, value: String| {
+        Line::Text(vec![(MARGIN, label.to_string()), (150.0, value)], false)
+    };
+    let mut lines = vec![Line::Title("Commuting expenses claim".to_string())];
+
+    lines.push(label(
+        "Employee:",
+        claim.employee.clone().unwrap_or_default(),
+    ));
+    lines.push(label(
+        "Period:",
+        claim
+            .period()
+            .map(|(start, end)| format!("{} - {}", start, end))
+            .unwrap_or_default(),
+    ));
+    lines.push(label("Route:", claim.route()));
+    lines.push(label("Invoice:", claim.invoice_reference()));
+    if let Some(card) = &claim.invoice.card_number {
+        lines.push(label("Card:", card.clone()));
+    }
+    lines.push(Line::Gap);
+
+    let header = ["Provider", "Date", "From", "To", "Price (EUR)"];
+    lines.push(Line::Text(
+        COLUMNS
+            .iter()
+            .zip(header)
+            .map(|(x, h)| (*x, h.to_string()))
+            .collect(),
+        true,
+    ));
+    lines.push(Line::Rule);
+    for trip in &claim.trips {
+        let cells = [
+            trip.provider.to_string(),
+            format_date(trip),
+            trip.from.clone(),
+            trip.to.clone(),
+            format!("{:.2}", trip.total()),
+        ];
+        lines.push(Line::Text(
+            COLUMNS.iter().copied().zip(cells).collect(),
+            false,
+        ));
+    }
+    // Other expenses have a description over the From and To columns instead of stations
+    for expense in &claim.other {
+        lines.push(Line::Text(
+            vec![
+                (COLUMNS[0], expense.category().to_string()),
+                (COLUMNS[1], expense.date().to_string()),
+                (COLUMNS[2], shorten(expense.description(), 55)),
+                (COLUMNS[4], format!("{:.2}", expense.amount())),
+            ],
+            false,
+        ));
+    }
+    lines.push(Line::Rule);
+
+    let price = COLUMNS[4];
+    for provider in [Provider::NS, Provider::GVB] {
+        let subtotal = claim.subtotal(provider.clone());
+        if subtotal != 0.0 {
+            lines.push(Line::Text(
+                vec![
+                    (COLUMNS[3], format!("{} subtotal", provider)),
+                    (price, format!("{:.2}", subtotal)),
+                ],
+                false,
+            ));
+        }
+    }
+    for category in claim.other_categories() {
+        lines.push(Line::Text(
+            vec![
+                (COLUMNS[3], format!("{} subtotal", category)),
+                (price, format!("{:.2}", claim.category_total(category))),
+            ],
+            false,
+        ));
+    }
+    lines.push(Line::Text(
+        vec![
+            (COLUMNS[3], "Total".to_string()),
+            (price, format!("{:.2}", claim.total())),
+        ],
+        true,
+    ));
+
+    // Signature block
+    lines.push(Line::Gap);
+    lines.push(Line::Gap);
+    lines.push(Line::Text(
+        vec![
+            (MARGIN, "Employee signature:".to_string()),
+            (300.0, "Approved by:".to_string()),
+        ],
+        false,
+    ));
+    lines.push(Line::Gap);
+    lines.push(Line::Gap);
+    lines.push(Line::Text(
+        vec![
+            (MARGIN, "______________________________".to_string()),
+            (300.0, "______________________________".to_string()),
+        ],
+        false,
+    ));
+    lines.push(Line::Text(
+        vec![(MARGIN, "Date:".to_string()), (300.0, "Date:".to_string())],
+        false,
+    ));
+    lines
+}
+
+// Text objects don't wrap, long descriptions would run into the price column
+fn shorten(text: String, chars: usize) -> String {
+    if text.chars().count() <= chars {
+        text
+    } else {
+        format!("{}...", text.chars().take(chars - 3).collect::<String>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Invoice, Trip};
+    use chrono::NaiveDate;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_layout_has_totals_and_signature() {
+        let date = NaiveDate::from_ymd_opt(2025, 3, 3).unwrap();
+        let trip = |provider, from: &str, to: &str, price| {
+            Trip::new(date, provider, from.to_string(), to.to_string(), price)
+        };
+        let trips = vec![
+            trip(Provider::NS, "Hilversum", "Amsterdam Centraal", 5.6),
+            trip(Provider::GVB, "Centraal Station", "Rokin", 1.2),
+        ];
+        let claim = Claim {
+            employee: Some("J. Jansen".to_string()),
+            to: vec!["Rokin".to_string()],
+            ..Claim::for_test(
+                Invoice::new(PathBuf::from("invoice.pdf"), Vec::new(), Vec::new()),
+                trips,
+            )
+        };
+
+        let text: Vec<String> = layout(&claim)
+            .into_iter()
+            .filter_map(|line| match line {
+                Line::Text(cells, _) => Some(cells.into_iter().map(|(_, t)| t).collect()),
+                _ => None,
+            })
+            .collect::<Vec<Vec<String>>>()
+            .concat();
+        assert!(text.contains(&"J. Jansen".to_string()));
+        assert!(text.contains(&"2025-03-03 - 2025-03-03".to_string()));
+        assert!(text.contains(&"GVB subtotal".to_string()));
+        assert!(text.contains(&"6.80".to_string()));
+        assert!(text.contains(&"Employee signature:".to_string()));
+    }
+}
@@ -1,88 +1,409 @@
-use chrono::{Datelike, NaiveDate};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use chrono::{Datelike, Duration, NaiveDate};
 
 use crate::data;
 
-enum Direction {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
     ToWork,
     ToHome,
-    None,
 }
 
-// There's no single reason to test this imho
-pub fn trip_workday_filter(all_trips: Vec<data::Trip>) -> Vec<data::Trip> {
-    let mut result: Vec<data::Trip> = Vec::new();
-    for trip in all_trips {
-        if trip.date.weekday().number_from_monday() <= 5 {
-            result.push(trip);
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::ToWork => write!(f, "to work"),
+            Direction::ToHome => write!(f, "to home"),
         }
     }
-    result
 }
 
-// Input trips should be sorted by date. Exactly like in PDF from NS
-pub fn trip_station_filter(
-    all_trips: Vec<data::Trip>,
-    from: Vec<String>,
-    to: Vec<String>,
-) -> Vec<data::Trip> {
-    let mut result: Vec<data::Trip> = Vec::new();
-    let mut tmp_trips: Vec<data::Trip> = Vec::new();
-    let mut in_chain = false;
-    let mut direction = Direction::None;
-    let mut current_date: chrono::NaiveDate = NaiveDate::from_yo_opt(2025, 1).unwrap();
-    let mut index = 0;
-    while index < all_trips.len() {
-        let trip = &all_trips[index];
-        if trip.price == 0.0 {
-            index += 1;
+/// Why a trip ends up in the claim or not
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+    Claimed {
+        journey: usize, // 1-based, in the order journeys happened
+        direction: Direction,
+        direct: bool, // Single leg journey
+    },
+    Incomplete,
+    ZeroPrice,
+    Refunded,
+    Weekend,
+    NotOnRoute,
+    ChainBroken {
+        at: usize, // Index of the last leg that could be chained
+    },
+    Missing {
+        direction: Direction, // Added with --claim-missing, not on the invoice
+    },
+    AlreadyClaimed {
+        claim: i64, // Id in the ledger
+        on: NaiveDate,
+    },
+}
+
+impl Decision {
+    pub fn is_claimed(&self) -> bool {
+        matches!(self, Decision::Claimed { .. } | Decision::Missing { .. })
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decision::Claimed {
+                journey,
+                direction,
+                direct: true,
+            } => write!(f, "direct match, journey {} ({})", journey, direction),
+            Decision::Claimed {
+                journey,
+                direction,
+                direct: false,
+            } => write!(f, "part of journey {} ({})", journey, direction),
+            Decision::Incomplete => write!(f, "dropped: missed check-out"),
+            Decision::ZeroPrice => write!(f, "dropped: zero price"),
+            Decision::Refunded => write!(f, "dropped: refunded"),
+            Decision::Weekend => write!(f, "dropped: weekend"),
+            Decision::NotOnRoute => write!(f, "dropped: not on route"),
+            Decision::ChainBroken { at } => {
+                write!(f, "dropped: chain broken at leg {}", at + 1)
+            }
+            Decision::Missing { direction } => {
+                write!(f, "added: missing direction ({})", direction)
+            }
+            Decision::AlreadyClaimed { claim, on } => {
+                write!(f, "dropped: already claimed on {} (claim {})", on, claim)
+            }
+        }
+    }
+}
+
+fn is_workday(date: NaiveDate) -> bool {
+    date.weekday().number_from_monday() <= 5
+}
+
+// Orders trips by check-in time within a day. Stable, so trips without times keep the
+// order of the source document.
+pub fn trip_time_sort(trips: &mut [data::Trip]) {
+    trips.sort_by_key(|trip| (trip.date, trip.check_in));
+}
+
+// Legs of one home<->work journey, as indices into the trips it was found in
+pub struct Journey {
+    pub direction: Direction,
+    pub legs: Vec<usize>,
+}
+
+// Every day is a graph where legs are nodes and a leg points to every leg that departs
+// where it arrived. A journey is a path from a leg leaving home (or work) to a leg arriving
+// at work (or home). The shortest path is taken first and its legs can't be reused, until
+// no path is left. Unrelated trips in between (a lunch ride) are simply not on the path.
+pub fn trip_journeys(
+    all_trips: &[data::Trip],
+    from: &[String],
+    to: &[String],
+    max_transfer_gap: Duration,
+) -> Vec<Journey> {
+    let mut days: BTreeMap<NaiveDate, Vec<usize>> = BTreeMap::new();
+    for (index, trip) in all_trips.iter().enumerate() {
+        // Free trips and trips that were refunded in full aren't claimable
+        if trip.price == 0.0 || trip.total() <= 0.0 {
             continue;
         }
-        if !in_chain {
-            current_date = trip.date;
-            if from.contains(&trip.from) && to.contains(&trip.to)
-                || from.contains(&trip.to) && to.contains(&trip.from)
+        days.entry(trip.date).or_default().push(index);
+    }
+
+    let mut journeys: Vec<Journey> = Vec::new();
+    for legs in days.values() {
+        let mut used: HashSet<usize> = HashSet::new();
+        loop {
+            let to_work = shortest_path(all_trips, legs, &used, from, to, max_transfer_gap);
+            let to_home = shortest_path(all_trips, legs, &used, to, from, max_transfer_gap);
+            let (direction, path) = match (to_work, to_home) {
+                (Some(to_work), Some(to_home)) if to_home.len() < to_work.len() => {
+                    (Direction::ToHome, to_home)
+                }
+                (Some(to_work), _) => (Direction::ToWork, to_work),
+                (None, Some(to_home)) => (Direction::ToHome, to_home),
+                (None, None) => break,
+            };
+            used.extend(path.iter().copied());
+            journeys.push(Journey {
+                direction,
+                legs: path,
+            });
+        }
+    }
+    journeys.sort_by_key(|journey| (all_trips[journey.legs[0]].date, journey.legs[0]));
+    journeys
+}
+
+// Breadth first search from all legs departing from start, so the first leg arriving at
+// end closes the journey with the fewest legs
+fn shortest_path(
+    all_trips: &[data::Trip],
+    legs: &[usize],
+    used: &HashSet<usize>,
+    start: &[String],
+    end: &[String],
+    max_transfer_gap: Duration,
+) -> Option<Vec<usize>> {
+    let mut previous: HashMap<usize, usize> = HashMap::new();
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    for &leg in legs {
+        if !used.contains(&leg) && start.contains(&all_trips[leg].from) {
+            visited.insert(leg);
+            queue.push_back(leg);
+        }
+    }
+    while let Some(leg) = queue.pop_front() {
+        if end.contains(&all_trips[leg].to) {
+            let mut path = vec![leg];
+            while let Some(&before) = previous.get(path.last().unwrap()) {
+                path.push(before);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for &next in legs {
+            if !used.contains(&next)
+                && !visited.contains(&next)
+                && can_transfer(&all_trips[leg], &all_trips[next], max_transfer_gap)
             {
-                direction = Direction::None;
-                in_chain = false;
-                result.push(trip.clone());
-            } else if from.contains(&trip.from) {
-                direction = Direction::ToWork;
-                tmp_trips.push(trip.clone());
-                in_chain = true;
-            } else if to.contains(&trip.from) {
-                direction = Direction::ToHome;
-                tmp_trips.push(trip.clone());
-                in_chain = true;
+                visited.insert(next);
+                previous.insert(next, leg);
+                queue.push_back(next);
             }
+        }
+    }
+    None
+}
+
+// Same rules as the claim itself: incomplete, free and refunded trips first, then the
+// journeys, then the workday filter. Whatever is left either started a chain from home or
+// work that couldn't be completed, or has nothing to do with the commute.
+pub fn trip_decisions(
+    all_trips: &[data::Trip],
+    from: &[String],
+    to: &[String],
+    max_transfer_gap: Duration,
+    include_incomplete: bool,
+) -> Vec<Decision> {
+    let mut decisions: Vec<Option<Decision>> = vec![None; all_trips.len()];
+    let mut candidates: Vec<usize> = Vec::new();
+    for (index, trip) in all_trips.iter().enumerate() {
+        decisions[index] = if !include_incomplete && trip.is_incomplete() {
+            Some(Decision::Incomplete)
+        } else if trip.price == 0.0 {
+            Some(Decision::ZeroPrice)
+        } else if trip.total() <= 0.0 {
+            Some(Decision::Refunded)
         } else {
-            if trip.date != current_date || tmp_trips.last().expect("Well, I don't know how tmp_trips can be empty here, but you achieved unachivable goal, congrats!").to != trip.from {
-                tmp_trips.clear();
-                in_chain = false;
+            candidates.push(index);
+            None
+        };
+    }
+
+    let candidate_trips: Vec<data::Trip> =
+        candidates.iter().map(|&i| all_trips[i].clone()).collect();
+    let mut journeys = trip_journeys(&candidate_trips, from, to, max_transfer_gap);
+    // A missed check-out can't be chained, the station it ended at is unknown. Claimed, it
+    // is a journey of its own when it leaves home or work (or arrives there).
+    if include_incomplete {
+        let used: HashSet<usize> = journeys.iter().flat_map(|j| j.legs.clone()).collect();
+        for (leg, trip) in candidate_trips.iter().enumerate() {
+            if used.contains(&leg) || !trip.is_incomplete() {
                 continue;
             }
-            tmp_trips.push(trip.clone());
-            match direction {
-                Direction::ToHome if from.contains(&trip.to) => {
-                    result.append(&mut tmp_trips);
-                    in_chain = false;
-                    direction = Direction::None;
-                }
-                Direction::ToWork if to.contains(&trip.to) => {
-                    result.append(&mut tmp_trips);
-                    in_chain = false;
-                    direction = Direction::None;
+            let direction = if from.contains(&trip.from) || to.contains(&trip.to) {
+                Direction::ToWork
+            } else if to.contains(&trip.from) || from.contains(&trip.to) {
+                Direction::ToHome
+            } else {
+                continue;
+            };
+            journeys.push(Journey {
+                direction,
+                legs: vec![leg],
+            });
+        }
+        journeys.sort_by_key(|journey| (candidate_trips[journey.legs[0]].date, journey.legs[0]));
+    }
+    let mut journey_number = 0;
+    for journey in journeys {
+        let workday = is_workday(candidate_trips[journey.legs[0]].date);
+        if workday {
+            journey_number += 1;
+        }
+        for &leg in &journey.legs {
+            decisions[candidates[leg]] = Some(if workday {
+                Decision::Claimed {
+                    journey: journey_number,
+                    direction: journey.direction,
+                    direct: journey.legs.len() == 1,
                 }
-                _ => {}
+            } else {
+                Decision::Weekend
+            });
+        }
+    }
+
+    // Follow what's left from every leg that leaves home or work as far as it goes
+    for &start in &candidates {
+        if decisions[start].is_some()
+            || !(from.contains(&all_trips[start].from) || to.contains(&all_trips[start].from))
+        {
+            continue;
+        }
+        let mut chain = vec![start];
+        while let Some(&next) = candidates.iter().find(|&&next| {
+            decisions[next].is_none()
+                && !chain.contains(&next)
+                && all_trips[next].date == all_trips[start].date
+                && can_transfer(
+                    &all_trips[*chain.last().unwrap()],
+                    &all_trips[next],
+                    max_transfer_gap,
+                )
+        }) {
+            chain.push(next);
+        }
+        if chain.len() > 1 {
+            let at = *chain.last().unwrap();
+            for leg in chain {
+                decisions[leg] = Some(Decision::ChainBroken { at });
             }
         }
-        index += 1;
     }
-    result
+
+    decisions
+        .into_iter()
+        .map(|decision| decision.unwrap_or(Decision::NotOnRoute))
+        .collect()
+}
+
+// Claimed trips in date order with their journey, and the others with the reason they were
+// left out. Legs added for a missing direction have no journey number.
+pub fn trip_claim_split(
+    all_trips: Vec<data::Trip>,
+    decisions: Vec<Decision>,
+) -> (Vec<data::Trip>, Vec<(data::Trip, Decision)>) {
+    let (claimed, excluded): (Vec<_>, Vec<_>) = all_trips
+        .into_iter()
+        .zip(decisions)
+        .partition(|(_, decision)| decision.is_claimed());
+    let mut claimed: Vec<data::Trip> = claimed
+        .into_iter()
+        .map(|(mut trip, decision)| {
+            if let Decision::Claimed {
+                journey, direction, ..
+            } = decision
+            {
+                trip.journey = Some((journey, direction));
+            }
+            trip
+        })
+        .collect();
+    claimed.sort_by_key(|trip| trip.date);
+    (claimed, excluded)
+}
+
+// Days where the claimed journeys don't come in pairs, with the direction that is missing.
+// Usually a leg that wasn't recognised or a missed check-out.
+pub fn trip_unbalanced_days(
+    all_trips: &[data::Trip],
+    decisions: &[Decision],
+) -> Vec<(NaiveDate, Direction)> {
+    let mut days: BTreeMap<NaiveDate, (HashSet<usize>, HashSet<usize>)> = BTreeMap::new();
+    for (trip, decision) in all_trips.iter().zip(decisions) {
+        if let Decision::Claimed {
+            journey, direction, ..
+        } = decision
+        {
+            let (to_work, to_home) = days.entry(trip.date).or_default();
+            match direction {
+                Direction::ToWork => to_work.insert(*journey),
+                Direction::ToHome => to_home.insert(*journey),
+            };
+        }
+    }
+    days.into_iter()
+        .filter_map(
+            |(date, (to_work, to_home))| match to_work.len().cmp(&to_home.len()) {
+                Ordering::Greater => Some((date, Direction::ToHome)),
+                Ordering::Less => Some((date, Direction::ToWork)),
+                Ordering::Equal => None,
+            },
+        )
+        .collect()
+}
+
+// Mirrors the first journey of the day that went the other way, for claiming a direction
+// that isn't on the invoice. The legs are reversed and marked as manual.
+pub fn trip_missing_direction(
+    all_trips: &[data::Trip],
+    decisions: &[Decision],
+    date: NaiveDate,
+    missing: Direction,
+) -> Vec<data::Trip> {
+    let journey = all_trips
+        .iter()
+        .zip(decisions)
+        .find_map(|(trip, decision)| match decision {
+            Decision::Claimed {
+                journey, direction, ..
+            } if trip.date == date && *direction != missing => Some(*journey),
+            _ => None,
+        });
+    let mut legs: Vec<data::Trip> = all_trips
+        .iter()
+        .zip(decisions)
+        .filter(|(_, decision)| {
+            matches!(decision, Decision::Claimed { journey: j, .. } if Some(*j) == journey)
+        })
+        .map(|(trip, _)| {
+            let mut leg = data::Trip::new(
+                trip.date,
+                trip.provider.clone(),
+                trip.to.clone(),
+                trip.from.clone(),
+                trip.total(),
+            );
+            leg.manual = true;
+            leg
+        })
+        .collect();
+    legs.reverse();
+    legs
+}
+
+fn can_transfer(previous: &data::Trip, next: &data::Trip, max_transfer_gap: Duration) -> bool {
+    legs_connect(previous, next)
+        && transfer_gap(previous, next)
+            .is_none_or(|gap| gap >= Duration::zero() && gap <= max_transfer_gap)
+}
+
+// Whether the next leg departs where the previous one arrived, regardless of the operator
+fn legs_connect(previous: &data::Trip, next: &data::Trip) -> bool {
+    data::same_interchange(&previous.to, &next.from)
+}
+
+// Time between arriving with one leg and checking in for the next, if both are known
+fn transfer_gap(previous: &data::Trip, next: &data::Trip) -> Option<Duration> {
+    let arrival = previous.check_out.or(previous.check_in)?;
+    Some(next.check_in? - arrival)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data::Provider;
 
     #[test]
     fn test_trip_station_filter_simple() {
@@ -122,7 +443,17 @@ mod tests {
         let from_stations = vec!["Hilversum".into()];
         let to_stations = vec!["Amsterdam Centraal".into(), "Amsterdam Zuid".into()];
 
-        let filtered = trip_station_filter(all_trips, from_stations, to_stations);
+        let journeys = trip_journeys(
+            &all_trips,
+            &from_stations,
+            &to_stations,
+            Duration::minutes(60),
+        );
+        let filtered: Vec<&data::Trip> = journeys
+            .iter()
+            .flat_map(|journey| &journey.legs)
+            .map(|&leg| &all_trips[leg])
+            .collect();
 
         assert_eq!(filtered.len(), 2);
         assert!(
@@ -182,7 +513,17 @@ mod tests {
         let from_stations = vec!["Hilversum".into()];
         let to_stations = vec!["Amsterdam Centraal".into()];
 
-        let filtered = trip_station_filter(all_trips, from_stations, to_stations);
+        let journeys = trip_journeys(
+            &all_trips,
+            &from_stations,
+            &to_stations,
+            Duration::minutes(60),
+        );
+        let filtered: Vec<&data::Trip> = journeys
+            .iter()
+            .flat_map(|journey| &journey.legs)
+            .map(|&leg| &all_trips[leg])
+            .collect();
 
         assert_eq!(filtered.len(), 4);
 
@@ -208,4 +549,476 @@ mod tests {
                 .any(|t| t.from == "Duivendrecht" && t.to == "Hilversum")
         );
     }
+
+    #[test]
+    fn test_trip_filter_max_transfer_gap() {
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 6, 24).unwrap();
+        let at = |h, m| chrono::NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+        let mut all_trips = vec![
+            data::Trip::new(
+                date,
+                Provider::NS,
+                "Duivendrecht".into(),
+                "Amsterdam Centraal".into(),
+                4.0,
+            )
+            .with_times(at(8, 40), Some(at(8, 55))), // match
+            data::Trip::new(
+                date,
+                Provider::NS,
+                "Hilversum".into(),
+                "Duivendrecht".into(),
+                3.0,
+            )
+            .with_times(at(8, 10), Some(at(8, 30))), // match
+            data::Trip::new(
+                date,
+                Provider::NS,
+                "Amsterdam Centraal".into(),
+                "Duivendrecht".into(),
+                4.0,
+            )
+            .with_times(at(17, 0), Some(at(17, 15))), // no match, spent the evening there
+            data::Trip::new(
+                date,
+                Provider::NS,
+                "Duivendrecht".into(),
+                "Hilversum".into(),
+                3.0,
+            )
+            .with_times(at(21, 0), Some(at(21, 20))), // no match
+        ];
+        trip_time_sort(&mut all_trips);
+
+        let from_stations = vec!["Hilversum".into()];
+        let to_stations = vec!["Amsterdam Centraal".into()];
+
+        let journeys = trip_journeys(
+            &all_trips,
+            &from_stations,
+            &to_stations,
+            Duration::minutes(60),
+        );
+        let filtered: Vec<&data::Trip> = journeys
+            .iter()
+            .flat_map(|journey| &journey.legs)
+            .map(|&leg| &all_trips[leg])
+            .collect();
+
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filtered[0].from, "Hilversum");
+        assert_eq!(filtered[1].to, "Amsterdam Centraal");
+    }
+
+    #[test]
+    fn test_trip_filter_multimodal() {
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 6, 24).unwrap();
+
+        let all_trips = vec![
+            data::Trip::new(
+                date,
+                Provider::NS,
+                "Hilversum".into(),
+                "Amsterdam Amstel".into(),
+                4.0,
+            ), // match
+            data::Trip::new(
+                date,
+                Provider::GVB,
+                "Amsterdam Amstel".into(),
+                "Rokin".into(),
+                1.5,
+            ), // match
+            data::Trip::new(date, Provider::GVB, "Rokin".into(), "Dam".into(), 1.0), // no match
+        ];
+
+        let from_stations = vec!["Hilversum".into()];
+        let to_stations = vec!["Rokin".into()];
+
+        let journeys = trip_journeys(
+            &all_trips,
+            &from_stations,
+            &to_stations,
+            Duration::minutes(60),
+        );
+        let filtered: Vec<&data::Trip> = journeys
+            .iter()
+            .flat_map(|journey| &journey.legs)
+            .map(|&leg| &all_trips[leg])
+            .collect();
+
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filtered[0].provider, Provider::NS);
+        assert_eq!(filtered[1].provider, Provider::GVB);
+    }
+
+    #[test]
+    fn test_trip_filter_interchange() {
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 6, 24).unwrap();
+
+        let all_trips = vec![
+            data::Trip::new(
+                date,
+                Provider::NS,
+                "Hilversum".into(),
+                "Amsterdam Centraal".into(),
+                5.6,
+            ), // match
+            data::Trip::new(
+                date,
+                Provider::GVB,
+                "Centraal Station".into(),
+                "Rokin".into(),
+                1.2,
+            ), // match
+            data::Trip::new(
+                date,
+                Provider::GVB,
+                "Rokin".into(),
+                "Station Zuid".into(),
+                1.4,
+            ), // match
+            data::Trip::new(
+                date,
+                Provider::NS,
+                "Amsterdam Zuid".into(),
+                "Hilversum".into(),
+                5.0,
+            ), // match
+        ];
+
+        let from_stations = vec!["Hilversum".into()];
+        let to_stations = vec!["Rokin".into()];
+
+        let journeys = trip_journeys(
+            &all_trips,
+            &from_stations,
+            &to_stations,
+            Duration::minutes(60),
+        );
+        let filtered: Vec<&data::Trip> = journeys
+            .iter()
+            .flat_map(|journey| &journey.legs)
+            .map(|&leg| &all_trips[leg])
+            .collect();
+
+        assert_eq!(filtered.len(), 4);
+    }
+
+    fn leg(provider: Provider, from: &str, to: &str) -> data::Trip {
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 6, 24).unwrap();
+        data::Trip::new(date, provider, from.into(), to.into(), 2.0)
+    }
+
+    fn route<'a>(trips: impl IntoIterator<Item = &'a data::Trip>) -> Vec<(&'a str, &'a str)> {
+        trips
+            .into_iter()
+            .map(|t| (t.from.as_str(), t.to.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_trip_filter_unrelated_trip_inside_chain() {
+        let all_trips = vec![
+            leg(Provider::NS, "Hilversum", "Amsterdam Centraal"), // match
+            leg(Provider::GVB, "Centraal Station", "Dam"),        // lunch, no match
+            leg(Provider::GVB, "Centraal Station", "Rokin"),      // match
+        ];
+
+        let journeys = trip_journeys(
+            &all_trips,
+            &["Hilversum".into()],
+            &["Rokin".into()],
+            Duration::minutes(60),
+        );
+        let filtered: Vec<&data::Trip> = journeys
+            .iter()
+            .flat_map(|journey| &journey.legs)
+            .map(|&leg| &all_trips[leg])
+            .collect();
+
+        assert_eq!(
+            route(filtered),
+            vec![
+                ("Hilversum", "Amsterdam Centraal"),
+                ("Centraal Station", "Rokin")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trip_filter_unordered_legs() {
+        let all_trips = vec![
+            leg(Provider::NS, "Duivendrecht", "Hilversum"), // match, evening
+            leg(Provider::NS, "Duivendrecht", "Amsterdam Zuid"), // match, morning
+            leg(Provider::NS, "Amsterdam Zuid", "Duivendrecht"), // match, evening
+            leg(Provider::NS, "Hilversum", "Duivendrecht"), // match, morning
+        ];
+
+        let journeys = trip_journeys(
+            &all_trips,
+            &["Hilversum".into()],
+            &["Amsterdam Zuid".into()],
+            Duration::minutes(60),
+        );
+        let filtered: Vec<&data::Trip> = journeys
+            .iter()
+            .flat_map(|journey| &journey.legs)
+            .map(|&leg| &all_trips[leg])
+            .collect();
+
+        assert_eq!(
+            route(filtered),
+            vec![
+                ("Amsterdam Zuid", "Duivendrecht"),
+                ("Duivendrecht", "Hilversum"),
+                ("Hilversum", "Duivendrecht"),
+                ("Duivendrecht", "Amsterdam Zuid"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trip_filter_chain_after_unrelated_start() {
+        let all_trips = vec![
+            leg(Provider::NS, "Utrecht Centraal", "Hilversum"), // no match
+            leg(Provider::NS, "Hilversum", "Duivendrecht"),     // no match, dead end
+            leg(Provider::NS, "Hilversum", "Amsterdam Zuid"),   // match
+            leg(Provider::NS, "Amsterdam Zuid", "Hilversum"),   // match
+        ];
+
+        let journeys = trip_journeys(
+            &all_trips,
+            &["Hilversum".into()],
+            &["Amsterdam Zuid".into()],
+            Duration::minutes(60),
+        );
+        let filtered: Vec<&data::Trip> = journeys
+            .iter()
+            .flat_map(|journey| &journey.legs)
+            .map(|&leg| &all_trips[leg])
+            .collect();
+
+        assert_eq!(
+            route(filtered),
+            vec![
+                ("Hilversum", "Amsterdam Zuid"),
+                ("Amsterdam Zuid", "Hilversum")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trip_filter_legs_used_once() {
+        let all_trips = vec![
+            leg(Provider::NS, "Hilversum", "Duivendrecht"), // match
+            leg(Provider::NS, "Duivendrecht", "Amsterdam Zuid"), // match
+            leg(Provider::NS, "Hilversum", "Duivendrecht"), // no match, nothing left to continue with
+        ];
+
+        let journeys = trip_journeys(
+            &all_trips,
+            &["Hilversum".into()],
+            &["Amsterdam Zuid".into()],
+            Duration::minutes(60),
+        );
+
+        assert_eq!(journeys.len(), 1);
+        assert_eq!(journeys[0].legs, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_trip_decisions() {
+        let saturday = chrono::NaiveDate::from_ymd_opt(2025, 6, 28).unwrap();
+        let mut refunded = leg(Provider::NS, "Hilversum", "Amsterdam Zuid");
+        refunded.adjustments.push(data::Adjustment {
+            kind: data::AdjustmentKind::Refund,
+            description: "Restitutie".into(),
+            amount: -2.0,
+            source: None,
+        });
+        let mut weekend = leg(Provider::NS, "Hilversum", "Amsterdam Zuid");
+        weekend.date = saturday;
+        let mut free = leg(Provider::GVB, "Centraal Station", "Dam");
+        free.price = 0.0;
+
+        let all_trips = vec![
+            leg(Provider::NS, "Hilversum", "Duivendrecht"),
+            leg(Provider::NS, "Duivendrecht", "Amsterdam Zuid"),
+            leg(Provider::NS, "Amsterdam Zuid", "Hilversum"),
+            refunded,
+            weekend,
+            free,
+            leg(Provider::NS, "Rotterdam Centraal", "Den Haag Centraal"),
+            leg(Provider::NS, "Hilversum", "Utrecht Centraal"),
+            leg(Provider::NS, "Utrecht Centraal", "Amersfoort Centraal"),
+            leg(Provider::NS, "Hilversum", ""),
+        ];
+
+        let decisions = trip_decisions(
+            &all_trips,
+            &["Hilversum".into()],
+            &["Amsterdam Zuid".into()],
+            Duration::minutes(60),
+            false,
+        );
+
+        let to_work = Decision::Claimed {
+            journey: 1,
+            direction: Direction::ToWork,
+            direct: false,
+        };
+        let to_home = Decision::Claimed {
+            journey: 2,
+            direction: Direction::ToHome,
+            direct: true,
+        };
+        assert_eq!(
+            decisions,
+            vec![
+                to_work.clone(),
+                to_work,
+                to_home,
+                Decision::Refunded,
+                Decision::Weekend,
+                Decision::ZeroPrice,
+                Decision::NotOnRoute,
+                Decision::ChainBroken { at: 8 },
+                Decision::ChainBroken { at: 8 },
+                Decision::Incomplete,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trip_decisions_include_incomplete() {
+        let mut missed = leg(Provider::NS, "Hilversum", "");
+        missed.price = 20.0;
+        let all_trips = vec![
+            missed,
+            leg(Provider::NS, "Amsterdam Zuid", "Hilversum"),
+            leg(Provider::NS, "Utrecht Centraal", ""),
+        ];
+        let decisions = |include_incomplete| {
+            trip_decisions(
+                &all_trips,
+                &["Hilversum".into()],
+                &["Amsterdam Zuid".into()],
+                Duration::minutes(60),
+                include_incomplete,
+            )
+        };
+        let to_home = |journey| Decision::Claimed {
+            journey,
+            direction: Direction::ToHome,
+            direct: true,
+        };
+
+        assert_eq!(
+            decisions(false),
+            vec![Decision::Incomplete, to_home(1), Decision::Incomplete]
+        );
+        assert_eq!(
+            decisions(true),
+            vec![
+                Decision::Claimed {
+                    journey: 1,
+                    direction: Direction::ToWork,
+                    direct: true,
+                },
+                to_home(2),
+                Decision::NotOnRoute,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trip_claim_split() {
+        let mut later = leg(Provider::NS, "Amsterdam Zuid", "Hilversum");
+        later.date = later.date.succ_opt().unwrap();
+        let all_trips = vec![
+            later,
+            leg(Provider::NS, "Hilversum", "Amsterdam Zuid"),
+            leg(Provider::GVB, "Rokin", "Centraal Station"),
+        ];
+        let decisions = vec![
+            Decision::Missing {
+                direction: Direction::ToHome,
+            },
+            Decision::Claimed {
+                journey: 1,
+                direction: Direction::ToWork,
+                direct: true,
+            },
+            Decision::NotOnRoute,
+        ];
+
+        let (claimed, excluded) = trip_claim_split(all_trips, decisions);
+        assert_eq!(
+            route(&claimed),
+            vec![
+                ("Hilversum", "Amsterdam Zuid"),
+                ("Amsterdam Zuid", "Hilversum")
+            ]
+        );
+        assert_eq!(claimed[0].journey, Some((1, Direction::ToWork)));
+        assert_eq!(claimed[1].journey, None);
+        assert_eq!(excluded.len(), 1);
+        assert_eq!(excluded[0].1, Decision::NotOnRoute);
+    }
+
+    #[test]
+    fn test_trip_unbalanced_days() {
+        let next_day = leg(Provider::NS, "Hilversum", "Amsterdam Zuid")
+            .date
+            .succ_opt()
+            .unwrap();
+        let mut next_day_out = leg(Provider::NS, "Hilversum", "Amsterdam Zuid");
+        next_day_out.date = next_day;
+        let mut next_day_back = leg(Provider::NS, "Amsterdam Zuid", "Hilversum");
+        next_day_back.date = next_day;
+        let all_trips = vec![
+            leg(Provider::NS, "Hilversum", "Duivendrecht"),
+            leg(Provider::NS, "Duivendrecht", "Amsterdam Zuid"),
+            next_day_out,
+            leg(Provider::NS, "Amsterdam Zuid", "Hilversum"),
+            next_day_back,
+        ];
+
+        let decisions = trip_decisions(
+            &all_trips,
+            &["Hilversum".into()],
+            &["Amsterdam Zuid".into()],
+            Duration::minutes(60),
+            false,
+        );
+
+        assert!(trip_unbalanced_days(&all_trips, &decisions).is_empty());
+
+        let decisions = trip_decisions(
+            &all_trips[..2],
+            &["Hilversum".into()],
+            &["Amsterdam Zuid".into()],
+            Duration::minutes(60),
+            false,
+        );
+        let unbalanced = trip_unbalanced_days(&all_trips[..2], &decisions);
+        assert_eq!(unbalanced, vec![(all_trips[0].date, Direction::ToHome)]);
+
+        let missing = trip_missing_direction(
+            &all_trips[..2],
+            &decisions,
+            unbalanced[0].0,
+            unbalanced[0].1,
+        );
+        assert_eq!(
+            route(&missing),
+            vec![
+                ("Amsterdam Zuid", "Duivendrecht"),
+                ("Duivendrecht", "Hilversum")
+            ]
+        );
+        assert!(missing.iter().all(|t| t.manual));
+    }
 }
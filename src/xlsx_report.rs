@@ -0,0 +1,297 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+use rust_xlsxwriter::{Format, Formula, Workbook, Worksheet};
+
+use crate::claim::Claim;
+use crate::data::{Provider, Trip};
+use crate::expense::Category;
+
+const CURRENCY: &str = "€ #,##0.00";
+
+/// Workbook for finance: the invoice, the claimed trips, totals as formulas over them and
+/// the trips that were left out
+pub fn xlsx_report(claim: &Claim, output: &Path) -> Result<()> {
+    workbook(claim)?.save(output)?;
+    Ok(())
+}
+
+fn workbook(claim: &Claim) -> Result<Workbook> {
+    let mut workbook = Workbook::new();
+    let header = Format::new().set_bold();
+    let date = Format::new().set_num_format("yyyy-mm-dd");
+    let time = Format::new().set_num_format("hh:mm");
+    let month = Format::new().set_num_format("yyyy-mm");
+    let currency = Format::new().set_num_format(CURRENCY);
+
+    // Where the trips come from, so finance can match the claim to the invoice
+    let invoice = &claim.invoice;
+    let sheet = workbook.add_worksheet().set_name("Invoice")?;
+    let labels = [
+        "File",
+        "Invoice number",
+        "Invoice date",
+        "Period start",
+        "Period end",
+        "Customer number",
+        "Card number",
+    ];
+    for (row, label) in labels.into_iter().enumerate() {
+        sheet.write_string_with_format(row as u32, 0, label, &header)?;
+    }
+    sheet.write_string(0, 1, invoice.file.display().to_string())?;
+    if let Some(number) = &invoice.number {
+        sheet.write_string(1, 1, number)?;
+    }
+    if let Some(day) = invoice.date {
+        sheet.write_datetime_with_format(2, 1, day, &date)?;
+    }
+    if let Some((start, end)) = invoice.period {
+        sheet.write_datetime_with_format(3, 1, start, &date)?;
+        sheet.write_datetime_with_format(4, 1, end, &date)?;
+    }
+    if let Some(customer) = &invoice.customer_number {
+        sheet.write_string(5, 1, customer)?;
+    }
+    if let Some(card) = &invoice.card_number {
+        sheet.write_string(6, 1, card)?;
+    }
+    set_widths(sheet, &[18.0, 40.0])?;
+
+    // Columns the Summary formulas refer to: A date, C provider, F price
+    let sheet = workbook.add_worksheet().set_name("Trips")?;
+    sheet.write_row_with_format(
+        0,
+        0,
+        ["Date", "Check-in", "Provider", "From", "To", "Price"],
+        &header,
+    )?;
+    for (index, trip) in claim.trips.iter().enumerate() {
+        let row = index as u32 + 1;
+        write_trip(sheet, row, trip, [&date, &time, &currency])?;
+    }
+    set_widths(sheet, &[12.0, 10.0, 10.0, 28.0, 28.0, 12.0])?;
+
+    // Columns the Summary formulas refer to: A date, B category, E amount
+    if !claim.other.is_empty() {
+        let sheet = workbook.add_worksheet().set_name("Expenses")?;
+        sheet.write_row_with_format(
+            0,
+            0,
+            ["Date", "Category", "Description", "VAT", "Amount"],
+            &header,
+        )?;
+        for (index, expense) in claim.other.iter().enumerate() {
+            let row = index as u32 + 1;
+            sheet.write_datetime_with_format(row, 0, expense.date(), &date)?;
+            sheet.write_string(row, 1, expense.category().to_string())?;
+            sheet.write_string(row, 2, expense.description())?;
+            if let Some((vat, _)) = expense.vat() {
+                sheet.write_number_with_format(row, 3, vat as f64, &currency)?;
+            }
+            sheet.write_number_with_format(row, 4, expense.amount() as f64, &currency)?;
+        }
+        set_widths(sheet, &[12.0, 12.0, 60.0, 12.0, 12.0])?;
+    }
+
+    // Excel computes the totals itself, the cached results are for viewers that don't
+    let mut columns = vec![
+        Column::Provider(Provider::NS),
+        Column::Provider(Provider::GVB),
+    ];
+    columns.extend(claim.other_categories().into_iter().map(Column::Category));
+    let total_col = columns.len() as u16 + 1;
+    let last_letter = (b'A' + columns.len() as u8) as char;
+    let sheet = workbook.add_worksheet().set_name("Summary")?;
+    sheet.write_string_with_format(0, 0, "Month", &header)?;
+    for (index, column) in columns.iter().enumerate() {
+        let label = match column {
+            Column::Provider(provider) => provider.to_string(),
+            Column::Category(category) => category.to_string(),
+        };
+        sheet.write_string_with_format(0, index as u16 + 1, label, &header)?;
+    }
+    sheet.write_string_with_format(0, total_col, "Total", &header)?;
+
+    let months: BTreeSet<(i32, u32)> = claim
+        .expenses()
+        .map(|expense| (expense.date().year(), expense.date().month()))
+        .collect();
+    for (index, &(year, number)) in months.iter().enumerate() {
+        let row = index as u32 + 1;
+        let first = NaiveDate::from_ymd_opt(year, number, 1).unwrap();
+        let in_month = |date: NaiveDate| (date.year(), date.month()) == (year, number);
+        sheet.write_datetime_with_format(row, 0, first, &month)?;
+        let mut month_total = 0.0;
+        for (index, column) in columns.iter().enumerate() {
+            let result: f32 = match column {
+                Column::Provider(provider) => claim
+                    .trips
+                    .iter()
+                    .filter(|t| t.provider == *provider && in_month(t.date))
+                    .map(|t| t.total())
+                    .sum(),
+                Column::Category(category) => claim
+                    .other
+                    .iter()
+                    .filter(|e| e.category() == *category && in_month(e.date()))
+                    .map(|e| e.amount())
+                    .sum(),
+            };
+            let formula =
+                Formula::new(month_formula(column, first)).set_result(format!("{:.2}", result));
+            sheet.write_formula_with_format(row, index as u16 + 1, formula, &currency)?;
+            month_total += result;
+        }
+        let formula = Formula::new(format!("SUM(B{0}:{1}{0})", row + 1, last_letter))
+            .set_result(format!("{:.2}", month_total));
+        sheet.write_formula_with_format(row, total_col, formula, &currency)?;
+    }
+    let total_row = months.len() as u32 + 1;
+    sheet.write_string_with_format(total_row, 0, "Total", &header)?;
+    let mut totals: Vec<f32> = columns
+        .iter()
+        .map(|column| match column {
+            Column::Provider(provider) => claim.subtotal(provider.clone()),
+            Column::Category(category) => claim.category_total(*category),
+        })
+        .collect();
+    totals.push(claim.total());
+    let total_format = currency.clone().set_bold();
+    for (index, result) in totals.into_iter().enumerate() {
+        let col = index as u16 + 1;
+        // Without months the range would be the total's own cell
+        if months.is_empty() {
+            sheet.write_number_with_format(total_row, col, 0.0, &total_format)?;
+            continue;
+        }
+        let letter = (b'B' + index as u8) as char;
+        let formula = Formula::new(format!("SUM({0}2:{0}{1})", letter, total_row))
+            .set_result(format!("{:.2}", result));
+        sheet.write_formula_with_format(total_row, col, formula, &total_format)?;
+    }
+    set_widths(sheet, &[12.0; 5])?;
+
+    let sheet = workbook.add_worksheet().set_name("Excluded")?;
+    sheet.write_row_with_format(
+        0,
+        0,
+        [
+            "Date", "Check-in", "Provider", "From", "To", "Price", "Reason",
+        ],
+        &header,
+    )?;
+    for (index, (trip, decision)) in claim.excluded.iter().enumerate() {
+        let row = index as u32 + 1;
+        write_trip(sheet, row, trip, [&date, &time, &currency])?;
+        sheet.write_string(row, 6, decision.to_string())?;
+    }
+    set_widths(sheet, &[12.0, 10.0, 10.0, 28.0, 28.0, 12.0, 32.0])?;
+
+    Ok(workbook)
+}
+
+// Typed cells, so the sheet can be sorted and summed as is
+fn write_trip(sheet: &mut Worksheet, row: u32, trip: &Trip, formats: [&Format; 3]) -> Result<()> {
+    let [date, time, currency] = formats;
+    sheet.write_datetime_with_format(row, 0, trip.date, date)?;
+    if let Some(check_in) = trip.check_in {
+        sheet.write_datetime_with_format(row, 1, check_in.time(), time)?;
+    }
+    sheet.write_string(row, 2, trip.provider.to_string())?;
+    sheet.write_string(row, 3, &trip.from)?;
+    sheet.write_string(row, 4, &trip.to)?;
+    sheet.write_number_with_format(row, 5, trip.total() as f64, currency)?;
+    Ok(())
+}
+
+// Summary columns: trips per provider, other expenses per category
+enum Column {
+    Provider(Provider),
+    Category(Category),
+}
+
+// Dates are compared as dates, a text month would depend on the locale Excel runs in
+fn month_formula(column: &Column, first: NaiveDate) -> String {
+    let next = first.checked_add_months(chrono::Months::new(1)).unwrap();
+    let (sheet, amount, key, value) = match column {
+        Column::Provider(provider) => ("Trips", "F", "C", provider.to_string()),
+        Column::Category(category) => ("Expenses", "E", "B", category.to_string()),
+    };
+    format!(
+        "SUMIFS({0}!${1}:${1},{0}!${2}:${2},\"{3}\",{0}!$A:$A,\">=\"&DATE({4},{5},1),{0}!$A:$A,\"<\"&DATE({6},{7},1))",
+        sheet,
+        amount,
+        key,
+        value,
+        first.year(),
+        first.month(),
+        next.year(),
+        next.month()
+    )
+}
+
+fn set_widths(sheet: &mut Worksheet, widths: &[f64]) -> Result<()> {
+    for (col, width) in widths.iter().enumerate() {
+        sheet.set_column_width(col as u16, *width)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Invoice;
+    use std::io::{Cursor, Read};
+    use std::path::PathBuf;
+
+    // Summary is the third sheet when there are no other expenses
+    fn summary_xml(claim: &Claim) -> String {
+        let buffer = workbook(claim).unwrap().save_to_buffer().unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(buffer)).unwrap();
+        let mut xml = String::new();
+        archive
+            .by_name("xl/worksheets/sheet3.xml")
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+        xml
+    }
+
+    #[test]
+    fn test_summary_totals() {
+        let invoice = || Invoice::new(PathBuf::from("invoice.pdf"), Vec::new(), Vec::new());
+        let date = NaiveDate::from_ymd_opt(2025, 3, 3).unwrap();
+        let trip = Trip::new(
+            date,
+            Provider::NS,
+            "Hilversum".to_string(),
+            "Amsterdam Centraal".to_string(),
+            5.6,
+        );
+
+        let xml = summary_xml(&Claim::for_test(invoice(), vec![trip]));
+        assert!(xml.contains("<f>SUM(B2:B2)</f>"));
+        assert!(xml.contains("<f>SUM(D2:D2)</f>"));
+
+        // Everything claimed before, nothing left
+        let xml = summary_xml(&Claim::for_test(invoice(), Vec::new()));
+        assert!(!xml.contains("<f>"));
+        assert!(xml.contains("<v>0</v>"));
+    }
+
+    #[test]
+    fn test_month_formula_spans_year_end() {
+        let first = NaiveDate::from_ymd_opt(2024, 12, 1).unwrap();
+        assert_eq!(
+            month_formula(&Column::Provider(Provider::GVB), first),
+            "SUMIFS(Trips!$F:$F,Trips!$C:$C,\"GVB\",Trips!$A:$A,\">=\"&DATE(2024,12,1),Trips!$A:$A,\"<\"&DATE(2025,1,1))"
+        );
+        assert_eq!(
+            month_formula(&Column::Category(Category::Internet), first),
+            "SUMIFS(Expenses!$E:$E,Expenses!$B:$B,\"Internet\",Expenses!$A:$A,\">=\"&DATE(2024,12,1),Expenses!$A:$A,\"<\"&DATE(2025,1,1))"
+        );
+    }
+}